@@ -0,0 +1,181 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+use crate::color::{CellColor, ColorMode};
+use crate::display::{compose_frame, Screen};
+
+// Where finished frames go. The game loop only talks to this trait, so the
+// same loop can drive the terminal, record to a file or run as a benchmark.
+pub trait RenderBackend {
+    fn present(&mut self, screen: &Screen);
+}
+
+// Keeps the last frame written to the terminal and only sends the cells that
+// changed since then, moving the cursor with ANSI escapes
+pub struct TerminalBackend {
+    color_mode: ColorMode,
+    previous: Vec<Vec<(char, CellColor)>>,
+    out: Vec<u8>,
+}
+
+impl TerminalBackend {
+    pub fn new(color_mode: ColorMode) -> TerminalBackend {
+        TerminalBackend {
+            color_mode,
+            previous: vec![],
+            out: vec![],
+        }
+    }
+
+    // the escapes and characters that turn the last frame into this one
+    fn encode(&mut self, screen: &Screen) {
+        let frame = compose_frame(screen, self.color_mode);
+        self.out.clear();
+        // the terminal's attributes are unknown between frames, start from a reset
+        let mut current_color = CellColor::default();
+        self.out.extend_from_slice(self.color_mode.sgr(&current_color).as_bytes());
+        if frame.len() != self.previous.len() || frame.first().map(|r| r.len()) != self.previous.first().map(|r| r.len()) {
+            // first frame or resize, nothing on the terminal can be reused
+            self.out.extend_from_slice(b"\x1b[2J");
+            self.previous = frame.iter().map(|r| vec![('\0', CellColor::default()); r.len()]).collect();
+        }
+
+        let mut utf8 = [0; 4];
+        for (r, row) in frame.iter().enumerate() {
+            let mut cursor: Option<usize> = None;
+            for (c, &cell) in row.iter().enumerate() {
+                if self.previous[r][c] == cell {
+                    continue;
+                }
+                if cursor != Some(c) {
+                    self.out.extend_from_slice(format!("\x1b[{};{}H", r + 1, c + 1).as_bytes());
+                }
+                if cell.1 != current_color {
+                    current_color = cell.1;
+                    self.out.extend_from_slice(self.color_mode.sgr(&current_color).as_bytes());
+                }
+                self.out.extend_from_slice(cell.0.encode_utf8(&mut utf8).as_bytes());
+                cursor = Some(c + 1);
+            }
+        }
+        self.out.extend_from_slice(self.color_mode.sgr(&CellColor::default()).as_bytes());
+        self.previous = frame;
+    }
+}
+
+impl RenderBackend for TerminalBackend {
+    fn present(&mut self, screen: &Screen) {
+        self.encode(screen);
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(&self.out).unwrap();
+        stdout.flush().unwrap();
+    }
+}
+
+// Appends every frame as plain text to a file, each one under a header line
+pub struct FileBackend {
+    writer: BufWriter<File>,
+    frame_count: usize,
+}
+
+impl FileBackend {
+    pub fn new(path: &str) -> io::Result<FileBackend> {
+        let file = File::create(path)?;
+        Ok(FileBackend {
+            writer: BufWriter::new(file),
+            frame_count: 0,
+        })
+    }
+}
+
+impl RenderBackend for FileBackend {
+    fn present(&mut self, screen: &Screen) {
+        self.frame_count += 1;
+        write!(self.writer, "--- frame {} ---\n{}", self.frame_count, screen.to_snapshot())
+            .expect("Unable to write frame");
+        self.writer.flush().expect("Unable to write frame");
+    }
+}
+
+// Keeps every frame as its text snapshot, for looking at what was drawn
+// after the loop is done
+pub struct MemoryBackend {
+    pub frames: Vec<String>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> MemoryBackend {
+        MemoryBackend { frames: vec![] }
+    }
+}
+
+impl RenderBackend for MemoryBackend {
+    fn present(&mut self, screen: &Screen) {
+        self.frames.push(screen.to_snapshot());
+    }
+}
+
+// Throws frames away, for measuring the cost of rendering alone
+pub struct NullBackend;
+
+impl NullBackend {
+    pub fn new() -> NullBackend {
+        NullBackend
+    }
+}
+
+impl RenderBackend for NullBackend {
+    fn present(&mut self, _screen: &Screen) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgb;
+    use crate::display::RenderMode;
+
+    fn encoded(backend: &mut TerminalBackend, screen: &Screen) -> String {
+        backend.encode(screen);
+        String::from_utf8(backend.out.clone()).unwrap()
+    }
+
+    #[test]
+    fn only_changed_cells_are_sent() {
+        let mut backend = TerminalBackend::new(ColorMode::NoColor);
+        let mut screen = Screen::new(3, 2, RenderMode::Ascii);
+        assert_eq!(encoded(&mut backend, &screen), "\x1b[2J\x1b[1;1H   \x1b[2;1H   ");
+        assert_eq!(encoded(&mut backend, &screen), "");
+        // y = 0 is the bottom row of the terminal
+        screen.set(1, 0, b'#', CellColor::default());
+        screen.set(2, 0, b'#', CellColor::default());
+        assert_eq!(encoded(&mut backend, &screen), "\x1b[2;2H##");
+    }
+
+    #[test]
+    fn resize_clears_and_sends_everything() {
+        let mut backend = TerminalBackend::new(ColorMode::NoColor);
+        encoded(&mut backend, &Screen::new(3, 2, RenderMode::Ascii));
+        assert_eq!(encoded(&mut backend, &Screen::new(2, 1, RenderMode::Ascii)), "\x1b[2J\x1b[1;1H  ");
+    }
+
+    #[test]
+    fn colors_are_only_set_when_they_change() {
+        let mut backend = TerminalBackend::new(ColorMode::TrueColor);
+        let mut screen = Screen::new(3, 1, RenderMode::Ascii);
+        encoded(&mut backend, &screen);
+        let red = CellColor { fg: Some(Rgb::new(255, 0, 0)), bg: None };
+        screen.set(0, 0, b'a', red);
+        screen.set(1, 0, b'b', red);
+        assert_eq!(encoded(&mut backend, &screen), "\x1b[0m\x1b[1;1H\x1b[0;38;2;255;0;0mab\x1b[0m");
+    }
+
+    #[test]
+    fn memory_keeps_every_frame() {
+        let mut backend = MemoryBackend::new();
+        let mut screen = Screen::new(3, 2, RenderMode::Ascii);
+        backend.present(&screen);
+        screen.set(0, 1, b'#', CellColor::default());
+        backend.present(&screen);
+        assert_eq!(backend.frames, vec!["\n\n", "#\n\n"]);
+    }
+}
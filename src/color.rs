@@ -0,0 +1,203 @@
+use std::env;
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+// None means the terminal's default color
+#[derive(Copy, Clone, PartialEq, Default)]
+pub struct CellColor {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+    NoColor,
+}
+
+// The 16 standard colors in the order of their SGR codes (30-37, then 90-97)
+const ANSI16: [Rgb; 16] = [
+    Rgb { r: 0, g: 0, b: 0 },
+    Rgb { r: 170, g: 0, b: 0 },
+    Rgb { r: 0, g: 170, b: 0 },
+    Rgb { r: 170, g: 85, b: 0 },
+    Rgb { r: 0, g: 0, b: 170 },
+    Rgb { r: 170, g: 0, b: 170 },
+    Rgb { r: 0, g: 170, b: 170 },
+    Rgb { r: 170, g: 170, b: 170 },
+    Rgb { r: 85, g: 85, b: 85 },
+    Rgb { r: 255, g: 85, b: 85 },
+    Rgb { r: 85, g: 255, b: 85 },
+    Rgb { r: 255, g: 255, b: 85 },
+    Rgb { r: 85, g: 85, b: 255 },
+    Rgb { r: 255, g: 85, b: 255 },
+    Rgb { r: 85, g: 255, b: 255 },
+    Rgb { r: 255, g: 255, b: 255 },
+];
+
+// Levels of each channel in the 6x6x6 cube of the 256 color palette
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Rgb {
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+
+    // 0.0 gives black, 1.0 gives the color unchanged
+    pub fn scale(&self, factor: f32) -> Rgb {
+        let factor = factor.clamp(0.0, 1.0);
+        Rgb {
+            r: (self.r as f32 * factor) as u8,
+            g: (self.g as f32 * factor) as u8,
+            b: (self.b as f32 * factor) as u8,
+        }
+    }
+
+    // 0.0 gives self, 1.0 gives other
+    pub fn lerp(&self, other: &Rgb, t: f32) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgb {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+        }
+    }
+
+    fn distance_squared(&self, other: &Rgb) -> i32 {
+        let dr = self.r as i32 - other.r as i32;
+        let dg = self.g as i32 - other.g as i32;
+        let db = self.b as i32 - other.b as i32;
+        dr * dr + dg * dg + db * db
+    }
+
+    pub fn ansi256_index(&self) -> u8 {
+        let nearest_level = |v: u8| {
+            let mut best = 0;
+            for (i, level) in CUBE_LEVELS.iter().enumerate() {
+                if (*level as i32 - v as i32).abs() < (CUBE_LEVELS[best] as i32 - v as i32).abs() {
+                    best = i;
+                }
+            }
+            best
+        };
+        let (ri, gi, bi) = (nearest_level(self.r), nearest_level(self.g), nearest_level(self.b));
+        let cube = Rgb::new(CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+        // the grayscale ramp is 232..=255, from 8 to 238 in steps of 10
+        let average = (self.r as i32 + self.g as i32 + self.b as i32) / 3;
+        let gray_index = ((average - 8).max(0) / 10).min(23);
+        let gray_level = (8 + gray_index * 10) as u8;
+        let gray = Rgb::new(gray_level, gray_level, gray_level);
+
+        if gray.distance_squared(self) < cube.distance_squared(self) {
+            return 232 + gray_index as u8;
+        }
+        (16 + 36 * ri + 6 * gi + bi) as u8
+    }
+
+    pub fn ansi16_index(&self) -> u8 {
+        let mut best = 0;
+        for (i, color) in ANSI16.iter().enumerate() {
+            if color.distance_squared(self) < ANSI16[best].distance_squared(self) {
+                best = i;
+            }
+        }
+        best as u8
+    }
+}
+
+impl ColorMode {
+    // NO_COLOR wins over everything, then COLORTERM, then TERM
+    pub fn detect() -> ColorMode {
+        if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+            return ColorMode::NoColor;
+        }
+        let colorterm = env::var("COLORTERM").unwrap_or_default().to_lowercase();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorMode::TrueColor;
+        }
+        let term = env::var("TERM").unwrap_or_default().to_lowercase();
+        if term.is_empty() || term == "dumb" {
+            return ColorMode::NoColor;
+        }
+        if term.contains("256color") {
+            return ColorMode::Ansi256;
+        }
+        ColorMode::Ansi16
+    }
+
+    // SGR sequence that resets the attributes and then selects `color`
+    pub fn sgr(&self, color: &CellColor) -> String {
+        if *self == ColorMode::NoColor {
+            return String::new();
+        }
+        let mut sequence = String::from("\x1b[0");
+        if let Some(fg) = color.fg {
+            sequence.push_str(&self.code(&fg, false));
+        }
+        if let Some(bg) = color.bg {
+            sequence.push_str(&self.code(&bg, true));
+        }
+        sequence.push('m');
+        sequence
+    }
+
+    fn code(&self, color: &Rgb, background: bool) -> String {
+        let layer = if background { 48 } else { 38 };
+        match self {
+            ColorMode::TrueColor => format!(";{};2;{};{};{}", layer, color.r, color.g, color.b),
+            ColorMode::Ansi256 => format!(";{};5;{}", layer, color.ansi256_index()),
+            ColorMode::Ansi16 => {
+                let index = color.ansi16_index();
+                let base = match (background, index < 8) {
+                    (false, true) => 30,
+                    (false, false) => 90 - 8,
+                    (true, true) => 40,
+                    (true, false) => 100 - 8,
+                };
+                format!(";{}", base + index as i32)
+            },
+            ColorMode::NoColor => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ansi256_corners_of_the_cube() {
+        assert_eq!(Rgb::new(0, 0, 0).ansi256_index(), 16);
+        assert_eq!(Rgb::new(255, 0, 0).ansi256_index(), 196);
+        assert_eq!(Rgb::new(255, 255, 255).ansi256_index(), 231);
+        // each channel on a level of the cube, 1, 2 and 3
+        assert_eq!(Rgb::new(95, 135, 175).ansi256_index(), 67);
+    }
+
+    // grays between the levels of the cube are nearer the grayscale ramp
+    #[test]
+    fn ansi256_grays_use_the_ramp() {
+        assert_eq!(Rgb::new(128, 128, 128).ansi256_index(), 244);
+        assert_eq!(Rgb::new(238, 238, 238).ansi256_index(), 255);
+        assert_eq!(Rgb::new(10, 10, 10).ansi256_index(), 232);
+    }
+
+    #[test]
+    fn sgr_in_each_mode() {
+        let color = CellColor { fg: Some(Rgb::new(255, 0, 0)), bg: Some(Rgb::new(0, 0, 0)) };
+        assert_eq!(ColorMode::TrueColor.sgr(&color), "\x1b[0;38;2;255;0;0;48;2;0;0;0m");
+        assert_eq!(ColorMode::Ansi256.sgr(&color), "\x1b[0;38;5;196;48;5;16m");
+        // 170, 0, 0 is nearer than the bright 255, 85, 85
+        assert_eq!(ColorMode::Ansi16.sgr(&color), "\x1b[0;31;40m");
+        assert_eq!(ColorMode::NoColor.sgr(&color), "");
+    }
+}
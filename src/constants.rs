@@ -1,4 +1,5 @@
 pub const SCALE: usize = 1;
+// fallback size when stdout isn't a terminal
 pub const WIDTH: usize = 160 * SCALE;
 pub const HEIGHT: usize = 48 * SCALE;
 pub const FPS: usize = 60;
@@ -1,26 +1,266 @@
-use crate::constants::{HEIGHT, WIDTH};
-const BORDER: [u8; WIDTH + 2] = [b'='; WIDTH + 2];
+use crate::color::{CellColor, ColorMode, Rgb};
+use crate::point::Point3d;
 
 pub trait Shape2d {
-    fn add_to_grid(&self, grid: &mut [[u8; WIDTH]; HEIGHT]);
+    fn add_to_grid(&self, screen: &mut Screen);
 }
 
+// What a 2D shape draws with. Without a depth the pixel is always drawn and
+// the depth buffer is left alone, with one it is depth tested like 3D shapes.
+#[derive(Copy, Clone)]
 pub struct Pixel {
     pub character: u8,
     pub z: Option<i32>,
+    pub color: CellColor,
 }
 
+impl Pixel {
+    pub fn plot(&self, screen: &mut Screen, x: i32, y: i32) {
+        if !screen.contains(x, y) {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        match self.z {
+            None => screen.set(x, y, self.character, self.color),
+            Some(z) => {
+                let z = z as f32;
+                if screen.z_buf[y][x].is_none_or(|pixel_z| z < pixel_z) {
+                    screen.set(x, y, self.character, self.color);
+                    screen.z_buf[y][x] = Some(z);
+                }
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum RenderMode {
+    // one pixel per terminal cell
+    Ascii,
+    // two pixels per cell, drawn with upper/lower half block characters
+    HalfBlock,
+    // 2x4 dots per cell, drawn with braille characters
+    Braille,
+}
+
+// A pixel buffer covering `cols` x `rows` terminal cells, `width` x `height`
+// pixels depending on the render mode. Row 0 is the bottom of the screen.
 pub struct Screen {
-    pub grid: [[u8; WIDTH]; HEIGHT],
-    pub z_buf: [[Option<f32>; WIDTH]; HEIGHT],
+    pub cols: usize,
+    pub rows: usize,
+    pub mode: RenderMode,
+    pub width: usize,
+    pub height: usize,
+    pub grid: Vec<Vec<u8>>,
+    pub color: Vec<Vec<CellColor>>,
+    pub z_buf: Vec<Vec<Option<f32>>>,
+    // world position of the triangle surface seen in each pixel, for passes
+    // after rasterization such as shadows
+    pub world: Vec<Vec<Option<Point3d>>>,
+    // terminal cells covered by the 2D overlay, row 0 at the top
+    pub overlay: Vec<Vec<Option<(char, CellColor)>>>,
 }
 
-pub fn print_grid(screen: &Screen) {
-    unsafe {
-        println!("\n\n\n\n\n\n\n{}", std::str::from_utf8_unchecked(&BORDER));
-        for line in screen.grid.iter().rev() {
-            println!("{}", std::str::from_utf8_unchecked(line));
+// bit of each dot in a braille character, by row from the top and column
+const BRAILLE_DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+// color of a pixel with a glyph but no color in the block modes, and what
+// fog fades such a glyph from
+pub const DEFAULT_PIXEL: Rgb = Rgb { r: 200, g: 200, b: 200 };
+
+impl RenderMode {
+    // pixels per terminal cell, (x, y)
+    pub fn cell_size(&self) -> (usize, usize) {
+        match self {
+            RenderMode::Ascii => (1, 1),
+            RenderMode::HalfBlock => (1, 2),
+            RenderMode::Braille => (2, 4),
+        }
+    }
+
+    pub fn next(&self) -> RenderMode {
+        match self {
+            RenderMode::Ascii => RenderMode::HalfBlock,
+            RenderMode::HalfBlock => RenderMode::Braille,
+            RenderMode::Braille => RenderMode::Ascii,
         }
-        println!("{}", std::str::from_utf8_unchecked(&BORDER));
+    }
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize, mode: RenderMode) -> Screen {
+        let (cell_width, cell_height) = mode.cell_size();
+        let (width, height) = (cols * cell_width, rows * cell_height);
+        Screen {
+            cols,
+            rows,
+            mode,
+            width,
+            height,
+            grid: vec![vec![b' '; width]; height],
+            color: vec![vec![CellColor::default(); width]; height],
+            z_buf: vec![vec![None; width]; height],
+            world: vec![vec![None; width]; height],
+            overlay: vec![vec![None; cols]; rows],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for row in &mut self.grid {
+            row.fill(b' ');
+        }
+        for row in &mut self.color {
+            row.fill(CellColor::default());
+        }
+        for row in &mut self.z_buf {
+            row.fill(None);
+        }
+        for row in &mut self.world {
+            row.fill(None);
+        }
+        for row in &mut self.overlay {
+            row.fill(None);
+        }
+    }
+
+    // writes a cell without any depth test
+    pub fn set(&mut self, x: usize, y: usize, character: u8, color: CellColor) {
+        self.grid[y][x] = character;
+        self.color[y][x] = color;
+    }
+
+    // covers a terminal cell with an overlay character, ignored off screen
+    pub fn set_overlay(&mut self, col: usize, row: usize, character: char, color: CellColor) {
+        if col < self.cols && row < self.rows {
+            self.overlay[row][col] = Some((character, color));
+        }
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize, mode: RenderMode) {
+        if cols != self.cols || rows != self.rows || mode != self.mode {
+            *self = Screen::new(cols, rows, mode);
+        }
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32
+    }
+
+    // Plain text picture of the screen as it would appear in the terminal,
+    // top row first, without colors and without trailing spaces
+    pub fn to_snapshot(&self) -> String {
+        let mut text = String::new();
+        for r in 0..self.rows {
+            let line: String = (0..self.cols).map(|c| self.cell(c, r, ColorMode::NoColor).0).collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    // the color a pixel shows in the block modes, None if nothing was drawn
+    fn pixel_color(&self, x: usize, y: usize) -> Option<Rgb> {
+        let color = self.color[y][x];
+        match (color.fg, color.bg, self.grid[y][x]) {
+            (Some(fg), _, _) => Some(fg),
+            (None, Some(bg), _) => Some(bg),
+            (None, None, b' ') => None,
+            (None, None, _) => Some(DEFAULT_PIXEL),
+        }
+    }
+
+    // The terminal cell at column `c`, row `r` counted from the top
+    fn cell(&self, c: usize, r: usize, color_mode: ColorMode) -> (char, CellColor) {
+        if let Some(overlay) = self.overlay[r][c] {
+            return overlay;
+        }
+        match self.mode {
+            RenderMode::Ascii => {
+                let y = self.height - 1 - r;
+                (self.grid[y][c] as char, self.color[y][c])
+            },
+            RenderMode::HalfBlock => {
+                let upper = self.pixel_color(c, self.height - 1 - 2 * r);
+                let lower = self.pixel_color(c, self.height - 2 - 2 * r);
+                half_block(upper, lower, color_mode)
+            },
+            RenderMode::Braille => {
+                let mut dots: u32 = 0;
+                let mut color = CellColor::default();
+                for (dy, row_bits) in BRAILLE_DOTS.iter().enumerate() {
+                    let y = self.height - 1 - (4 * r + dy);
+                    for (dx, bit) in row_bits.iter().enumerate() {
+                        let x = 2 * c + dx;
+                        if self.grid[y][x] != b' ' {
+                            if dots == 0 {
+                                color = CellColor { fg: self.color[y][x].fg, bg: None };
+                            }
+                            dots |= bit;
+                        }
+                    }
+                }
+                match dots {
+                    0 => (' ', CellColor::default()),
+                    _ => (char::from_u32(0x2800 + dots).unwrap(), color),
+                }
+            },
+        }
+    }
+}
+
+fn half_block(upper: Option<Rgb>, lower: Option<Rgb>, color_mode: ColorMode) -> (char, CellColor) {
+    if color_mode == ColorMode::NoColor {
+        let character = match (upper, lower) {
+            (Some(_), Some(_)) => '█',
+            (Some(_), None) => '▀',
+            (None, Some(_)) => '▄',
+            (None, None) => ' ',
+        };
+        return (character, CellColor::default());
+    }
+    match (upper, lower) {
+        (Some(_), _) => ('▀', CellColor { fg: upper, bg: lower }),
+        (None, Some(_)) => ('▄', CellColor { fg: lower, bg: None }),
+        (None, None) => (' ', CellColor::default()),
+    }
+}
+
+// The whole terminal picture, top row first
+pub fn compose_frame(screen: &Screen, color_mode: ColorMode) -> Vec<Vec<(char, CellColor)>> {
+    let mut frame: Vec<Vec<(char, CellColor)>> = vec![];
+    for r in 0..screen.rows {
+        frame.push((0..screen.cols).map(|c| screen.cell(c, r, color_mode)).collect());
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_follows_the_mode() {
+        let mut screen = Screen::new(4, 3, RenderMode::Ascii);
+        screen.set(0, 0, b'#', CellColor::default());
+        // same size and mode, nothing is thrown away
+        screen.resize(4, 3, RenderMode::Ascii);
+        assert_eq!(screen.grid[0][0], b'#');
+
+        screen.resize(4, 3, RenderMode::Braille);
+        assert_eq!((screen.cols, screen.rows, screen.width, screen.height), (4, 3, 8, 12));
+        assert_eq!(screen.grid[0][0], b' ');
+        screen.resize(5, 2, RenderMode::HalfBlock);
+        assert_eq!((screen.width, screen.height, screen.overlay.len()), (5, 4, 2));
+    }
+
+    #[test]
+    fn contains_the_pixels_not_the_cells() {
+        let screen = Screen::new(3, 2, RenderMode::HalfBlock);
+        assert!(screen.contains(0, 0));
+        assert!(screen.contains(2, 3));
+        assert!(!screen.contains(3, 0));
+        assert!(!screen.contains(0, 4));
+        assert!(!screen.contains(-1, 0));
+        assert!(!screen.contains(0, -1));
     }
 }
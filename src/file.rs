@@ -1,41 +1,132 @@
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use crate::point::Point3d;
-use crate::triangle::Triangle3d;
+use crate::triangle::{Culling, Triangle3d};
 use crate::line::Line3d;
 use crate::projection::Shape3d;
+use crate::color::Rgb;
+use crate::light::{self, Shading};
+use crate::material::Material;
+use crate::texture::Texture;
 
-pub fn read_data_file(path: &str) -> Vec<Shape3d> {
+// Materials and textures read so far, shapes that use the same one share it
+pub struct Library {
+    pub materials: HashMap<String, Rc<Material>>,
+    // each named material without its border, for the pieces of bisected
+    // triangles whose border is drawn by lines of its own
+    pub borderless: HashMap<String, Rc<Material>>,
+    pub textures: HashMap<PathBuf, Rc<Texture>>,
+    // texture paths are relative to this, the directory of the file read
+    pub dir: PathBuf,
+}
+
+impl Library {
+    pub fn new() -> Library {
+        Library { materials: HashMap::new(), borderless: HashMap::new(), textures: HashMap::new(), dir: PathBuf::new() }
+    }
+
+    // for reading the file at `path`, with its textures found next to it
+    pub fn for_file(path: &str) -> Library {
+        let dir = Path::new(path).parent().map(Path::to_path_buf).unwrap_or_default();
+        Library { dir, ..Library::new() }
+    }
+
+    pub fn add_material(&mut self, material: Material) {
+        let borderless = Material { border: None, ..material.clone() };
+        self.borderless.insert(material.name.clone(), Rc::new(borderless));
+        self.materials.insert(material.name.clone(), Rc::new(material));
+    }
+
+    // `material` without a border. Named materials share the one made with
+    // them, a material changed by a shape's own parts is only used by that
+    // shape and is copied.
+    pub fn without_border(&self, material: &Rc<Material>) -> Rc<Material> {
+        if material.border.is_none() {
+            return material.clone();
+        }
+        if let Some(named) = self.materials.get(&material.name)
+            && Rc::ptr_eq(named, material)
+        {
+            return self.borderless[&material.name].clone();
+        }
+        Rc::new(Material { border: None, ..(**material).clone() })
+    }
+
+    pub fn texture(&mut self, path: &str) -> Result<Rc<Texture>, String> {
+        let path = self.dir.join(path);
+        if let Some(texture) = self.textures.get(&path) {
+            return Ok(texture.clone());
+        }
+        let texture = Rc::new(Texture::load(&path)?);
+        self.textures.insert(path, texture.clone());
+        Ok(texture)
+    }
+}
+
+// One shape per line, or a material block:
+//   TRI:m{<material>}(x, y, z)(x, y, z)(x, y, z)[u, v][u, v][u, v]
+//   TRI:b{<border>}f{<fill>}c{<culling>}t{<texture>}(x, y, z)(x, y, z)(x, y, z)
+//   LIN:m{<material>}(x, y, z)(x, y, z)
+//   LIN:b{<character>}(x, y, z)(x, y, z)
+//   MAT:<name>{
+//   fill <glyph>
+//   border <glyph or N>
+//   color <r> <g> <b>
+//   background <r> <g> <b>
+//   border_color <r> <g> <b>
+//   shading <none, flat or smooth>
+//   culling <none, back or front>
+//   transparency <0.0 to 1.0>
+//   texture <path>
+//   }
+// A material has to come before the shapes that use it, every line of its
+// block is optional. The parts in braces before the points are optional
+// too, after an m{} they change that shape alone. A border of N means none,
+// c{} is B to cull the back, F the front or N (the default) for nothing, t{}
+// is the path of a texture file (see texture.rs) drawn with the [u, v] of
+// each point. Texture paths are relative to the directory of the file. The
+// front of a triangle is the side its points go around clockwise when looked
+// at, so on closed meshes list them clockwise as seen from outside.
+pub fn read_data_file(path: &str) -> Result<Vec<Shape3d>, String> {
     let mut data = String::new();
-    let mut f = File::open(path).expect("Unable to open file");
-    f.read_to_string(&mut data).expect("Unable to read string");
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut data))
+        .map_err(|e| format!("Unable to read {}: {}", path, e))?;
     let lines: Vec<&str> = data.split("\n").filter(|l| l.len() > 0).collect();
 
     let mut shapes: Vec<Shape3d> = vec![];
-    for l in lines {
+    let mut library = Library::for_file(path);
+    let mut i = 0;
+    while i < lines.len() {
+        let l = lines[i];
         match &l[0..3] {
             "TRI" => {
-                let t = string_to_triangle(l);
+                let t = string_to_triangle(l, &mut library)?;
 
-                if let Some(b) = t.border {
-                    shapes.push(Shape3d::Line(Line3d {p1:t.points[0], p2:t.points[1], character: b}));
-                    shapes.push(Shape3d::Line(Line3d {p1:t.points[0], p2:t.points[2], character: b}));
-                    shapes.push(Shape3d::Line(Line3d {p1:t.points[1], p2:t.points[2], character: b}));
+                if t.material.border.is_some() {
+                    shapes.push(Shape3d::Line(Line3d {p1:t.points[0], p2:t.points[1], material: t.material.clone()}));
+                    shapes.push(Shape3d::Line(Line3d {p1:t.points[0], p2:t.points[2], material: t.material.clone()}));
+                    shapes.push(Shape3d::Line(Line3d {p1:t.points[1], p2:t.points[2], material: t.material.clone()}));
                 }
 
+                // the lines above are the border, the pieces of the bisected
+                // triangle mustn't draw their own
+                let material = library.without_border(&t.material);
                 let mut vec: Vec<Shape3d> = vec![];
                 bisect_triangle(Triangle3d {
-                    points: t.points,
-                    fill: t.fill,
-                    border: None,
+                    material,
+                    ..t
                 }, &mut vec);
                 for s in vec {
                     shapes.push(s);
                 }
             },
             "LIN" => {
-                let l = string_to_line(l);
+                let l = string_to_line(l, &mut library)?;
 
                 let mut vec: Vec<Shape3d> = vec![];
                 bisect_line(l, &mut vec);
@@ -43,9 +134,23 @@ pub fn read_data_file(path: &str) -> Vec<Shape3d> {
                     shapes.push(s);
                 }
             },
-            _ => panic!("Error in format of file {}", path),
+            "MAT" => {
+                let name = l[4..].trim().trim_end_matches("{").trim();
+                let start = i + 1;
+                while i < lines.len() && lines[i].trim() != "}" {
+                    i += 1;
+                }
+                if i == lines.len() {
+                    return Err(format!("Material {} isn't closed in file {}", name, path));
+                }
+                let material = read_material(name, &lines[start..i], &mut library)?;
+                library.add_material(material);
+            },
+            _ => return Err(format!("Error in format of file {}", path)),
         }
+        i += 1;
     }
+    light::compute_vertex_normals(&mut shapes);
     // let mut tmp: Vec<Shape3d> = vec![];
     // for s in &mut shapes {
     //     match s {
@@ -60,19 +165,85 @@ pub fn read_data_file(path: &str) -> Vec<Shape3d> {
     // for s in tmp {
         // shapes.push(s);
     // }
-    return shapes;
+    Ok(shapes)
+}
+
+pub fn read_material(name: &str, properties: &[&str], library: &mut Library) -> Result<Material, String> {
+    let mut material = Material { name: name.to_string(), ..Material::default() };
+    for property in properties {
+        let property = property.trim_start();
+        let (key, value) = property.split_once(" ").unwrap_or((property, ""));
+        // a glyph can be a space, so only the other values are trimmed
+        let glyph = *value.as_bytes().first().unwrap_or(&b' ');
+        let value = value.trim();
+        // exactly three channels, from 0 to 255
+        let rgb = || {
+            let bad = || format!("Bad color \"{}\" in material {}, it needs three values from 0 to 255", property, name);
+            let channels: Vec<u8> = value
+                .split_whitespace()
+                .map(|c| c.parse::<u8>().map_err(|_| bad()))
+                .collect::<Result<_, _>>()?;
+            match channels[..] {
+                [r, g, b] => Ok(Rgb::new(r, g, b)),
+                _ => Err(bad()),
+            }
+        };
+        match key {
+            "fill" => material.fill = glyph,
+            "border" => material.border = if value == "N" { None } else { Some(glyph) },
+            "color" => material.color.fg = Some(rgb()?),
+            "background" => material.color.bg = Some(rgb()?),
+            "border_color" => material.border_color.fg = Some(rgb()?),
+            "shading" => material.shading = match value {
+                "none" => Shading::None,
+                "flat" => Shading::Flat,
+                "smooth" => Shading::Smooth,
+                _ => return Err(format!("Unknown shading {} in material {}", value, name)),
+            },
+            "culling" => material.culling = match value {
+                "none" => Culling::None,
+                "back" => Culling::Back,
+                "front" => Culling::Front,
+                _ => return Err(format!("Unknown culling {} in material {}", value, name)),
+            },
+            "transparency" => material.transparency = value.parse::<f32>().map_err(|_| format!("Bad transparency {} in material {}", value, name))?,
+            "texture" => material.texture = Some(library.texture(value).map_err(|e| format!("{} in material {}", e, name))?),
+            _ => return Err(format!("Unknown property {} in material {}", key, name)),
+        }
+    }
+    Ok(material)
 }
 
-pub fn string_to_triangle(text: &str) -> Triangle3d {
+// The material from the parts in braces of a shape's line, the text before
+// its points
+fn parts_to_material(parts: &[&str], text: &str, library: &mut Library) -> Result<Rc<Material>, String> {
+    let mut material = Rc::new(Material::default());
+    for part in parts {
+        match part.split_once("{") {
+            Some(("m", name)) => material = library.materials.get(name).ok_or_else(|| format!("Unknown material {} in {}", name, text))?.clone(),
+            Some(("b", "N")) => Rc::make_mut(&mut material).border = None,
+            Some(("b", border)) => Rc::make_mut(&mut material).border = Some(border.as_bytes()[0]),
+            Some(("f", fill)) => Rc::make_mut(&mut material).fill = fill.as_bytes()[0],
+            Some(("c", "B")) => Rc::make_mut(&mut material).culling = Culling::Back,
+            Some(("c", "F")) => Rc::make_mut(&mut material).culling = Culling::Front,
+            Some(("c", "N")) => Rc::make_mut(&mut material).culling = Culling::None,
+            Some(("t", path)) => Rc::make_mut(&mut material).texture = Some(library.texture(path).map_err(|e| format!("{} in {}", e, text))?),
+            _ => return Err(format!("Unknown part {}}} in {}", part, text)),
+        }
+    }
+    Ok(material)
+}
+
+pub fn string_to_triangle(text: &str, library: &mut Library) -> Result<Triangle3d, String> {
 
-    let split = text.split("}").collect::<Vec<&str>>();
-    let border: Option<u8> = match split[0].chars().nth_back(0).unwrap() as u8 {
-        b'N' => None,
-        letter => Some(letter),
+    let split = text[4..].split("}").collect::<Vec<&str>>();
+    let material = parts_to_material(&split[..split.len() - 1], text, library)?;
+    let (points_str, uvs_str) = match split[split.len() - 1].split_once("[") {
+        Some((points_str, uvs_str)) => (points_str, Some(uvs_str)),
+        None => (split[split.len() - 1], None),
     };
-    let fill: u8 = split[1].chars().nth_back(0).unwrap() as u8;
 
-    let points_vec: Vec<Point3d> = split[2]
+    let points_vec: Vec<Point3d> = points_str
         .split(")")
         .filter(|l| l.len() > 0)
         .map(|s| &s.trim()[1..])
@@ -95,18 +266,34 @@ pub fn string_to_triangle(text: &str) -> Triangle3d {
         points_vec[2].clone(),
     ];
 
-    return Triangle3d {
+    let uvs = uvs_str.map(|uvs_str| {
+        let uvs_vec: Vec<(f32, f32)> = uvs_str
+            .split(['[', ']'])
+            .filter(|s| !s.trim().is_empty())
+            .map(|uv_str| {
+                let uv_split: Vec<f32> = uv_str
+                    .split(",")
+                    .map(|s| s.trim().parse::<f32>().unwrap())
+                    .collect();
+                (uv_split[0], uv_split[1])
+            })
+            .collect();
+        [uvs_vec[0], uvs_vec[1], uvs_vec[2]]
+    });
+
+    Ok(Triangle3d {
         points: points_arr,
-        fill,
-        border,
-    };
+        normals: None,
+        uvs,
+        material,
+    })
 }
 
-pub fn string_to_line(text: &str) -> Line3d {
-    let split = text.split("}").collect::<Vec<&str>>();
-    let character: u8 = split[0].chars().nth_back(0).unwrap() as u8;
+pub fn string_to_line(text: &str, library: &mut Library) -> Result<Line3d, String> {
+    let split = text[4..].split("}").collect::<Vec<&str>>();
+    let material = parts_to_material(&split[..split.len() - 1], text, library)?;
 
-    let points_vec: Vec<Point3d> = split[1]
+    let points_vec: Vec<Point3d> = split[split.len() - 1]
         .split(")")
         .filter(|l| l.len() > 0)
         .map(|s| &s.trim()[1..])
@@ -123,60 +310,39 @@ pub fn string_to_line(text: &str) -> Line3d {
         })
         .collect();
 
-    return Line3d {
+    Ok(Line3d {
         p1: points_vec[0],
         p2: points_vec[1],
-        character,
-    };
+        material,
+    })
 }
 
 pub fn bisect_triangle(t: Triangle3d, vec: &mut Vec<Shape3d>) {
-    let s1 = Line3d{p1: t.points[0], p2: t.points[1], character:0};
-    let s2 = Line3d{p1: t.points[0], p2: t.points[2], character:0};
-    let s3 = Line3d{p1: t.points[1], p2: t.points[2], character:0};
+    let s1 = Line3d{p1: t.points[0], p2: t.points[1], material: t.material.clone()};
+    let s2 = Line3d{p1: t.points[0], p2: t.points[2], material: t.material.clone()};
+    let s3 = Line3d{p1: t.points[1], p2: t.points[2], material: t.material.clone()};
     let (l1, l2, l3) = (s1.length(), s2.length(), s3.length());
     if l1 <= 400.0 && l2 <= 400.0 && l3 <= 400.0 {
         vec.push(Shape3d::Triangle(t));
         return;
     }
-    println!("l1: {}, l2: {}, l3: {}", l1, l2, l3);
-
-    if l1 >= l2 && l1 >= l3 {
-        let m = s1.center();
-        bisect_triangle(Triangle3d {
-            points: [t.points[0], m, t.points[2]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
-        bisect_triangle(Triangle3d {
-            points: [m, t.points[1], t.points[2]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
+
+    // the longest edge, from corner a to b, is split at its middle, index 3
+    // in the halves. Each half keeps the winding of t, so culling still sees
+    // the same front.
+    let (m, a, b, halves) = if l1 >= l2 && l1 >= l3 {
+        (s1.center(), 0, 1, [[0, 3, 2], [3, 1, 2]])
     } else if l2 >= l3 {
-        let m = s2.center();
-        bisect_triangle(Triangle3d {
-            points: [t.points[0], m, t.points[1]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
-        bisect_triangle(Triangle3d {
-            points: [m, t.points[1], t.points[2]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
+        (s2.center(), 0, 2, [[0, 1, 3], [3, 1, 2]])
     } else {
-        let m = s3.center();
-        bisect_triangle(Triangle3d {
-            points: [t.points[0], m, t.points[1]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
-        bisect_triangle(Triangle3d {
-            points: [m, t.points[0], t.points[2]],
-            fill: t.fill,
-            border: t.border,
-        }, vec);
+        (s3.center(), 1, 2, [[0, 1, 3], [0, 3, 2]])
+    };
+    let points = [t.points[0], t.points[1], t.points[2], m];
+    let uvs = t.uvs.map(|uv| [uv[0], uv[1], uv[2], ((uv[a].0 + uv[b].0) / 2.0, (uv[a].1 + uv[b].1) / 2.0)]);
+    for half in halves {
+        let mut h = t.with_points(half.map(|i| points[i]));
+        h.uvs = uvs.map(|uv| half.map(|i| uv[i]));
+        bisect_triangle(h, vec);
     }
 }
 
@@ -186,9 +352,102 @@ pub fn bisect_line(l: Line3d, vec: &mut Vec<Shape3d>) {
         vec.push(Shape3d::Line(l));
         return;
     }
-    let l1 = Line3d{p1: l.p1, p2: m, character: l.character};
-    let l2 = Line3d{p1: m, p2: l.p2, character: l.character};
+    let l1 = Line3d{p1: l.p1, p2: m, material: l.material.clone()};
+    let l2 = Line3d{p1: m, p2: l.p2, material: l.material.clone()};
     bisect_line(l1, vec);
     bisect_line(l2, vec);
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::CellColor;
+
+    #[test]
+    fn read_material_reads_every_property() {
+        let mut library = Library::for_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/water.dat"));
+        let block = [
+            "fill ~",
+            "border N",
+            "color 1 2 3",
+            "background 4 5 6",
+            "border_color 7 8 9",
+            "shading smooth",
+            "culling front",
+            "transparency 0.25",
+            "texture brick.txt",
+        ];
+        let material = read_material("water", &block, &mut library).unwrap();
+        assert_eq!(material.name, "water");
+        assert_eq!(material.fill, b'~');
+        assert_eq!(material.border, None);
+        assert!(material.color == CellColor { fg: Some(Rgb::new(1, 2, 3)), bg: Some(Rgb::new(4, 5, 6)) });
+        assert!(material.border_color.fg == Some(Rgb::new(7, 8, 9)));
+        assert!(material.shading == Shading::Smooth);
+        assert!(material.culling == Culling::Front);
+        assert_eq!(material.transparency, 0.25);
+        assert!(Rc::ptr_eq(material.texture.as_ref().unwrap(), &library.texture("brick.txt").unwrap()));
+    }
+
+    // a glyph can be a space, the rest of the block is left as the default
+    #[test]
+    fn read_material_keeps_blank_glyphs() {
+        let material = read_material("glass", &["fill  ", "border  "], &mut Library::new()).unwrap();
+        assert_eq!(material.fill, b' ');
+        assert_eq!(material.border, Some(b' '));
+        assert!(material.shading == Shading::None);
+        assert_eq!(material.transparency, 0.0);
+    }
+
+    #[test]
+    fn read_material_rejects_unknown_properties() {
+        let error = read_material("stone", &["hardness 9"], &mut Library::new()).err().unwrap();
+        assert_eq!(error, "Unknown property hardness in material stone");
+    }
+
+    #[test]
+    fn read_material_rejects_colors_without_three_values() {
+        for line in ["color 255 0", "background 1 2 3 4", "border_color 300 0 0", "color"] {
+            let error = read_material("glass", &["fill #", line], &mut Library::new()).err().unwrap();
+            assert!(error.contains(&format!("\"{}\" in material glass", line)), "{}", error);
+        }
+    }
+
+    // textures are found next to the file, wherever it's read from
+    #[test]
+    fn textures_are_relative_to_the_file() {
+        let dir = std::env::temp_dir().join("termgl_texture_dir");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("dots.txt"), ".:\n").unwrap();
+        let path = dir.join("dots.dat");
+        std::fs::write(&path, "MAT:dots{\ntexture dots.txt\n}\nTRI:m{dots}(0, 0, 0)(0, 9, 0)(9, 0, 0)[0, 0][0, 1][1, 0]\n").unwrap();
+        let shapes = read_data_file(path.to_str().unwrap()).unwrap();
+        let Some(Shape3d::Triangle(t)) = shapes.iter().find(|s| matches!(s, Shape3d::Triangle(_))) else { panic!() };
+        assert_eq!(t.material.texture.as_ref().unwrap().glyphs, vec![b".:".to_vec()]);
+
+        std::fs::write(&path, "TRI:t{missing.txt}(0, 0, 0)(0, 9, 0)(9, 0, 0)\n").unwrap();
+        let error = read_data_file(path.to_str().unwrap()).err().unwrap();
+        assert!(error.contains("missing.txt"), "{}", error);
+    }
+
+    #[test]
+    fn shapes_share_their_named_material() {
+        let path = std::env::temp_dir().join("termgl_shared_material.dat");
+        std::fs::write(&path, "MAT:stone{\nfill S\nborder +\n}\nTRI:m{stone}(0, 0, 0)(0, 900, 0)(900, 0, 0)\nTRI:m{stone}f{T}(0, 0, 0)(0, 90, 0)(90, 0, 0)\n").unwrap();
+        let shapes = read_data_file(path.to_str().unwrap()).unwrap();
+        let triangles: Vec<&Triangle3d> = shapes.iter().filter_map(|s| match s {
+            Shape3d::Triangle(t) => Some(t),
+            Shape3d::Line(_) => None,
+        }).collect();
+        // the big one is bisected into pieces that all share the borderless stone
+        let (stone, own): (Vec<&Triangle3d>, Vec<&Triangle3d>) = triangles.iter().partition(|t| t.material.fill == b'S');
+        assert!(stone.len() > 1);
+        assert!(stone.iter().all(|t| Rc::ptr_eq(&t.material, &stone[0].material) && t.material.border.is_none()));
+        // the other changed its fill, so it has a material of its own
+        assert_eq!(own.len(), 1);
+        assert!(own[0].material.border.is_none());
+        // its border is drawn as lines with the stone's border
+        assert!(shapes.iter().any(|s| matches!(s, Shape3d::Line(l) if l.material.border == Some(b'+'))));
+    }
+}
@@ -0,0 +1,124 @@
+use crate::color::Rgb;
+use crate::display::{Screen, DEFAULT_PIXEL};
+use crate::light::RAMP;
+
+#[derive(Copy, Clone)]
+pub enum FogMode {
+    // no fog before `start`, all fog from `end` on
+    Linear {
+        start: f32,
+        end: f32,
+    },
+    // 1 - e^(-density * (depth - start)) past `start`
+    Exponential {
+        start: f32,
+        density: f32,
+    },
+}
+
+// Fades what is far from the camera toward a background glyph and color
+#[derive(Copy, Clone)]
+pub struct Fog {
+    pub mode: FogMode,
+    pub glyph: u8,
+    pub color: Rgb,
+}
+
+impl Fog {
+    // How much of a pixel at this depth is fog, from 0.0 to 1.0
+    pub fn amount(&self, depth: f32) -> f32 {
+        let amount = match self.mode {
+            FogMode::Linear { start, end } => {
+                if end <= start {
+                    if depth >= start { 1.0 } else { 0.0 }
+                } else {
+                    (depth - start) / (end - start)
+                }
+            },
+            FogMode::Exponential { start, density } => 1.0 - (-density * (depth - start).max(0.0)).exp(),
+        };
+        amount.clamp(0.0, 1.0)
+    }
+
+    // Uses the depth left in the z buffer, pixels nothing was drawn on stay
+    // as they are. Glyphs on the light ramp step toward the fog glyph, others
+    // turn into it once the pixel is more fog than surface. Glyphs drawn
+    // without a color fade from DEFAULT_PIXEL, blank ones are left uncolored.
+    pub fn apply(&self, screen: &mut Screen) {
+        let fog_index = RAMP.iter().position(|g| *g == self.glyph);
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                let amount = match screen.z_buf[y][x] {
+                    Some(depth) => self.amount(depth),
+                    None => continue,
+                };
+                if amount <= 0.0 {
+                    continue;
+                }
+                let glyph = screen.grid[y][x];
+                screen.grid[y][x] = match (RAMP.iter().position(|g| *g == glyph), fog_index) {
+                    (Some(index), Some(fog_index)) => {
+                        let faded = index as f32 + (fog_index as f32 - index as f32) * amount;
+                        RAMP[faded.round() as usize]
+                    },
+                    _ => if amount >= 0.5 { self.glyph } else { glyph },
+                };
+                let color = &mut screen.color[y][x];
+                if color.fg.is_none() && glyph != b' ' {
+                    color.fg = Some(DEFAULT_PIXEL);
+                }
+                color.fg = color.fg.map(|c| c.lerp(&self.color, amount));
+                color.bg = color.bg.map(|c| c.lerp(&self.color, amount));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::CellColor;
+    use crate::display::RenderMode;
+
+    const FOG: Fog = Fog { mode: FogMode::Linear { start: 100.0, end: 300.0 }, glyph: b' ', color: Rgb { r: 0, g: 0, b: 0 } };
+
+    // one pixel drawn at `depth`
+    fn screen_with(glyph: u8, color: CellColor, depth: f32) -> Screen {
+        let mut screen = Screen::new(1, 1, RenderMode::Ascii);
+        screen.set(0, 0, glyph, color);
+        screen.z_buf[0][0] = Some(depth);
+        screen
+    }
+
+    #[test]
+    fn linear_amount() {
+        assert_eq!(FOG.amount(50.0), 0.0);
+        assert_eq!(FOG.amount(200.0), 0.5);
+        assert_eq!(FOG.amount(900.0), 1.0);
+    }
+
+    #[test]
+    fn uncolored_glyphs_fade_from_the_default() {
+        let mut screen = screen_with(b'@', CellColor::default(), 200.0);
+        FOG.apply(&mut screen);
+        assert!(screen.color[0][0].fg == Some(DEFAULT_PIXEL.lerp(&FOG.color, 0.5)));
+        // half way down the ramp from @ to blank, 4.5 steps rounded up
+        assert_eq!(screen.grid[0][0], b'+');
+    }
+
+    #[test]
+    fn blank_uncolored_pixels_stay_uncolored() {
+        let mut screen = screen_with(b' ', CellColor::default(), 200.0);
+        FOG.apply(&mut screen);
+        assert!(screen.color[0][0] == CellColor::default());
+    }
+
+    #[test]
+    fn near_pixels_are_untouched() {
+        let color = CellColor { fg: Some(Rgb::new(200, 0, 0)), bg: None };
+        let mut screen = screen_with(b'#', color, 50.0);
+        FOG.apply(&mut screen);
+        assert_eq!(screen.grid[0][0], b'#');
+        assert!(screen.color[0][0] == color);
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+
+use crate::color::CellColor;
+use crate::point::{self, Point3d, ORIGIN};
+use crate::projection::Shape3d;
+
+// From darkest to brightest, a lit face picks its fill from here
+pub const RAMP: &[u8] = b" .:-=+*#%@";
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Shading {
+    // the triangle's own fill, whatever the lighting
+    None,
+    // one glyph for the whole face from its normal
+    Flat,
+    // intensity from the vertex normals, interpolated over the face
+    Smooth,
+}
+
+// Directions are the way the light travels and don't need to be normalized.
+// Point and spot lights get dimmer with distance by 1 / (1 + falloff * d^2).
+#[derive(Copy, Clone)]
+pub enum Light {
+    Ambient {
+        intensity: f32,
+    },
+    Directional {
+        direction: Point3d,
+        intensity: f32,
+    },
+    Point {
+        position: Point3d,
+        intensity: f32,
+        falloff: f32,
+    },
+    // full intensity inside `inner_angle` of its direction, fading out
+    // towards `outer_angle`, both in radians from the center of the cone
+    Spot {
+        position: Point3d,
+        direction: Point3d,
+        intensity: f32,
+        falloff: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+}
+
+impl Light {
+    // How much this light adds at a surface point with the given normal.
+    // Faces are lit from both sides since they may be drawn from both sides,
+    // see triangle::Culling.
+    pub fn contribution(&self, position: &Point3d, normal: &Point3d) -> f32 {
+        match self {
+            Light::Ambient { intensity } => *intensity,
+            Light::Directional { direction, intensity } => {
+                let toward_light = ORIGIN.minus(direction).normalized();
+                intensity * normal.dot(&toward_light).abs()
+            },
+            Light::Point { position: light_position, intensity, falloff } => {
+                let toward_light = light_position.minus(position).normalized();
+                let distance = point::distance(light_position, position);
+                intensity * normal.dot(&toward_light).abs() / (1.0 + falloff * distance * distance)
+            },
+            Light::Spot { position: light_position, direction, intensity, falloff, inner_angle, outer_angle } => {
+                let toward_light = light_position.minus(position).normalized();
+                let angle = ORIGIN.minus(&toward_light).dot(&direction.normalized()).clamp(-1.0, 1.0).acos();
+                let cone = if angle <= *inner_angle {
+                    1.0
+                } else if angle >= *outer_angle {
+                    0.0
+                } else {
+                    (outer_angle - angle) / (outer_angle - inner_angle)
+                };
+                let distance = point::distance(light_position, position);
+                intensity * cone * normal.dot(&toward_light).abs() / (1.0 + falloff * distance * distance)
+            },
+        }
+    }
+}
+
+// Brightness of a surface point from all the lights, from 0.0 to 1.0
+pub fn intensity_at(lights: &[Light], position: &Point3d, normal: &Point3d) -> f32 {
+    let total: f32 = lights.iter().map(|l| l.contribution(position, normal)).sum();
+    total.clamp(0.0, 1.0)
+}
+
+// Brightest glyph of the ramp for an intensity, never the blank one so a lit
+// face doesn't disappear
+pub fn ramp_glyph(intensity: f32) -> u8 {
+    let last = RAMP.len() - 1;
+    let index = (intensity.clamp(0.0, 1.0) * last as f32).round() as usize;
+    RAMP[index.max(1)]
+}
+
+// The glyph `steps` further down the ramp. Like ramp_glyph it stops before
+// the blank one, blanks and glyphs that aren't on the ramp are kept.
+pub fn darken_glyph(glyph: u8, steps: usize) -> u8 {
+    match RAMP.iter().position(|g| *g == glyph) {
+        Some(0) | None => glyph,
+        Some(index) => RAMP[index.saturating_sub(steps).max(1)],
+    }
+}
+
+// Darkens the colors by the same intensity as the glyph
+pub fn shade_color(color: &CellColor, intensity: f32) -> CellColor {
+    CellColor {
+        fg: color.fg.map(|c| c.scale(intensity)),
+        bg: color.bg.map(|c| c.scale(intensity)),
+    }
+}
+
+// Gives every triangle normals at its corners, averaged over all faces that
+// share that corner, so smooth shading hides the edges between them. Faces
+// count as they are wound, see triangle::Culling, and bigger ones weigh more.
+pub fn compute_vertex_normals(shapes: &mut [Shape3d]) {
+    let key = |p: &Point3d| ((p.x * 100.0).round() as i64, (p.y * 100.0).round() as i64, (p.z * 100.0).round() as i64);
+
+    let mut sums: HashMap<(i64, i64, i64), Point3d> = HashMap::new();
+    for s in shapes.iter() {
+        if let Shape3d::Triangle(t) = s {
+            // twice the area long, along the normal
+            let weighted = t.points[1].minus(&t.points[0]).cross(&t.points[2].minus(&t.points[0]));
+            for p in &t.points {
+                let sum = sums.entry(key(p)).or_insert(Point3d { x: 0.0, y: 0.0, z: 0.0 });
+                sum.x += weighted.x;
+                sum.y += weighted.y;
+                sum.z += weighted.z;
+            }
+        }
+    }
+
+    for s in shapes.iter_mut() {
+        if let Shape3d::Triangle(t) = s {
+            let normal_at = |p: &Point3d| sums[&key(p)].normalized();
+            t.normals = Some([normal_at(&t.points[0]), normal_at(&t.points[1]), normal_at(&t.points[2])]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::testing::close;
+    use crate::triangle::Triangle3d;
+    use std::rc::Rc;
+
+    #[test]
+    fn ramp_glyph_never_blank() {
+        assert_eq!(ramp_glyph(0.0), b'.');
+        assert_eq!(ramp_glyph(-1.0), b'.');
+        assert_eq!(ramp_glyph(0.5), b'+');
+        assert_eq!(ramp_glyph(1.0), b'@');
+        assert_eq!(ramp_glyph(2.0), b'@');
+    }
+
+    fn triangle(points: [Point3d; 3]) -> Shape3d {
+        Shape3d::Triangle(Triangle3d { points, normals: None, uvs: None, material: Rc::new(Material::default()) })
+    }
+
+    fn p(x: f32, y: f32, z: f32) -> Point3d {
+        Point3d { x, y, z }
+    }
+
+    // the faces of a steep square pyramid, wound to face out
+    fn pyramid() -> Vec<Shape3d> {
+        let mut face = [p(-1.0, 0.0, 1.0), p(1.0, 0.0, 1.0), p(0.0, 10.0, 0.0)];
+        let mut faces = vec![];
+        for _ in 0..4 {
+            faces.push(triangle(face));
+            // a quarter turn around y
+            face = face.map(|q| p(q.z, q.y, -q.x));
+        }
+        faces
+    }
+
+    fn normals_at(shapes: &[Shape3d], point: &Point3d) -> Vec<Point3d> {
+        let mut normals = vec![];
+        for s in shapes {
+            let Shape3d::Triangle(t) = s else { continue };
+            for (corner, normal) in t.points.iter().zip(t.normals.unwrap()) {
+                if close(corner, point) {
+                    normals.push(normal);
+                }
+            }
+        }
+        normals
+    }
+
+    #[test]
+    fn pyramid_apex_normal_points_up() {
+        let mut shapes = pyramid();
+        compute_vertex_normals(&mut shapes);
+        let apex = normals_at(&shapes, &p(0.0, 10.0, 0.0));
+        assert_eq!(apex.len(), 4);
+        assert!(apex.iter().all(|n| close(n, &p(0.0, 1.0, 0.0))));
+
+        // whatever order the faces come in
+        let mut reversed = pyramid();
+        reversed.reverse();
+        compute_vertex_normals(&mut reversed);
+        assert!(close(&normals_at(&reversed, &p(1.0, 0.0, 1.0))[0], &normals_at(&shapes, &p(1.0, 0.0, 1.0))[0]));
+    }
+
+    // two faces folded along the z axis share the normal half way between
+    // theirs on the fold, the other corners keep their own
+    #[test]
+    fn vertex_normals_average_the_faces() {
+        let mut shapes = vec![
+            triangle([p(0.0, 0.0, 0.0), p(1.0, 0.0, 0.0), p(0.0, 0.0, 1.0)]),
+            triangle([p(0.0, 0.0, 0.0), p(0.0, 1.0, 0.0), p(0.0, 0.0, 1.0)]),
+        ];
+        compute_vertex_normals(&mut shapes);
+        let half = 0.5_f32.sqrt();
+        assert!(normals_at(&shapes, &p(0.0, 0.0, 1.0)).iter().all(|n| close(n, &p(half, -half, 0.0))));
+        assert!(close(&normals_at(&shapes, &p(1.0, 0.0, 0.0))[0], &p(0.0, -1.0, 0.0)));
+        assert!(close(&normals_at(&shapes, &p(0.0, 1.0, 0.0))[0], &p(1.0, 0.0, 0.0)));
+    }
+
+    fn near(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    const UP: Point3d = Point3d { x: 0.0, y: 1.0, z: 0.0 };
+
+    // 1 / (1 + falloff * d^2), with d = 10 and falloff = 0.01 that's half
+    #[test]
+    fn point_light_falls_off_with_distance() {
+        let light = Light::Point { position: p(0.0, 10.0, 0.0), intensity: 1.0, falloff: 0.01 };
+        assert!(near(light.contribution(&ORIGIN, &UP), 0.5));
+        // lit from both sides
+        assert!(near(light.contribution(&ORIGIN, &p(0.0, -1.0, 0.0)), 0.5));
+        let no_falloff = Light::Point { position: p(0.0, 10.0, 0.0), intensity: 1.0, falloff: 0.0 };
+        assert!(near(no_falloff.contribution(&ORIGIN, &UP), 1.0));
+    }
+
+    #[test]
+    fn spot_light_fades_between_its_angles() {
+        let spot = Light::Spot {
+            position: p(0.0, 10.0, 0.0),
+            direction: p(0.0, -1.0, 0.0),
+            intensity: 1.0,
+            falloff: 0.0,
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+        };
+        assert!(near(spot.contribution(&ORIGIN, &UP), 1.0));
+        // half way between the inner and the outer angle
+        let halfway = p(10.0 * 0.4_f32.tan(), 0.0, 0.0);
+        assert!(near(spot.contribution(&halfway, &UP), 0.5 * 0.4_f32.cos()));
+        assert_eq!(spot.contribution(&p(10.0, 0.0, 0.0), &UP), 0.0);
+    }
+
+    // a face turned 60 degrees from the light gets half of it, the ambient
+    // term is added and the total is clamped
+    #[test]
+    fn directional_and_ambient_light() {
+        let lights = [
+            Light::Directional { direction: Point3d { x: 0.0, y: -2.0, z: 0.0 }, intensity: 1.0 },
+            Light::Ambient { intensity: 0.2 },
+        ];
+        let (sin, cos) = 60.0_f32.to_radians().sin_cos();
+        let tilted = Point3d { x: sin, y: cos, z: 0.0 };
+        assert!((intensity_at(&lights, &ORIGIN, &tilted) - 0.7).abs() < 1e-5);
+        assert_eq!(intensity_at(&lights, &ORIGIN, &Point3d { x: 0.0, y: 1.0, z: 0.0 }), 1.0);
+    }
+}
@@ -1,17 +1,25 @@
-use crate::constants::{HEIGHT, WIDTH};
 use crate::point::{self, Point2d, Point2dZ, Point3d};
-use crate::projection::Camera;
+use crate::projection::View;
 use crate::display::Screen;
+use crate::color::CellColor;
+use crate::material::Material;
+use crate::math::Transform;
+use std::rc::Rc;
 
 pub struct Line3d {
     pub p1: Point3d,
     pub p2: Point3d,
-    pub character: u8,
+    // drawn with the border glyph and color
+    pub material: Rc<Material>,
 }
 pub struct Line2dZ {
     pub p1: Point2dZ,
     pub p2: Point2dZ,
     pub character: u8,
+    pub color: CellColor,
+    // false for the edges of transparent shapes, which are depth tested but
+    // mustn't hide what is drawn behind them later
+    pub writes_depth: bool,
 }
 pub struct Line2d {
     pub p1: Point2d,
@@ -20,15 +28,10 @@ pub struct Line2d {
 }
 
 impl Line3d {
-    pub fn project(&self, camera: &Camera) -> Line2dZ {
-        let p1 = self.p1
-            .rotate_y(&camera.pos, camera.rot.x)
-            .rotate_x(&camera.pos, camera.rot.y)
-            .project(camera);
-        let p2 = self.p2
-            .rotate_y(&camera.pos, camera.rot.x)
-            .rotate_x(&camera.pos, camera.rot.y)
-            .project(camera);
+    // for lines that went through clip_near
+    pub fn project(&self, view: &View) -> Line2dZ {
+        let p1 = view.project_clipped(&self.p1);
+        let p2 = view.project_clipped(&self.p2);
         let (p1_unwrapped, p2_unwrapped) = match (p1, p2) {
             (Some(p1), Some(p2)) => (p1, p2),
             _ => (Point2dZ { x: -1, y: -1, z: 0 }, Point2dZ {x: -1, y: -1, z: 0 }),
@@ -36,8 +39,30 @@ impl Line3d {
         return Line2dZ {
             p1: p1_unwrapped,
             p2: p2_unwrapped,
-            character: self.character,
+            character: self.material.line_glyph(),
+            color: self.material.border_color,
+            writes_depth: self.material.transparency == 0.0,
+        };
+    }
+    // moved by a model transform, into world space
+    pub fn transformed(&self, transform: &Transform) -> Line3d {
+        Line3d { p1: transform.point(&self.p1), p2: transform.point(&self.p2), material: self.material.clone() }
+    }
+    // The part of the line in front of the camera's near plane, if any
+    pub fn clip_near(&self, view: &View) -> Option<Line3d> {
+        let (d1, d2) = (view.depth(&self.p1), view.depth(&self.p2));
+        if d1 < view.near && d2 < view.near {
+            return None;
+        }
+        let cut = |inside: &Point3d, outside: &Point3d, d_in: f32, d_out: f32| inside.lerp(outside, (d_in - view.near) / (d_in - d_out));
+        let (p1, p2) = if d1 < view.near {
+            (cut(&self.p2, &self.p1, d2, d1), self.p2)
+        } else if d2 < view.near {
+            (self.p1, cut(&self.p1, &self.p2, d1, d2))
+        } else {
+            (self.p1, self.p2)
         };
+        Some(Line3d { p1, p2, material: self.material.clone() })
     }
     pub fn center(&self) -> Point3d {
         Point3d {
@@ -49,19 +74,6 @@ impl Line3d {
     pub fn length(&self) -> f32 {
         point::distance(&self.p1, &self.p2)
     }
-   pub fn rotate_y_mut(&mut self, center: &Point3d, rads: f32) {
-       self.p1.rotate_y_mut(center, rads);
-       self.p2.rotate_y_mut(center, rads);
-   }
-   pub fn rotate_x_mut(&mut self, center: &Point3d, rads: f32) {
-       self.p1.rotate_x_mut(center, rads);
-       self.p2.rotate_x_mut(center, rads);
-   }
-   pub fn rotate_z_mut(&mut self, center: &Point3d, rads: f32) {
-       self.p1.rotate_z_mut(center, rads);
-       self.p2.rotate_z_mut(center, rads);
-   }
-
     pub fn add_to_grid(&self, screen: &mut Screen) {
         let (p1, p2) = (Point2d {x:self.p1.x as i32, y:self.p1.y as i32}, Point2d {x:self.p2.x as i32, y:self.p2.y as i32});
         // if !p1.is_in_screen() && !p2.is_in_screen() {
@@ -84,12 +96,8 @@ impl Line3d {
         let x_increment = if diff_x > 0 { 1 } else { -1 };
         let y_increment = if diff_y > 0 { 1 } else { -1 };
         while current_p.x != p2.x || current_p.y != p2.y {
-            if current_p.x >= 0
-                && current_p.x < WIDTH as i32
-                && current_p.y >= 0
-                && current_p.y < HEIGHT as i32
-            {
-                screen.grid[current_p.y as usize][current_p.x as usize] = self.character;
+            if screen.contains(current_p.x, current_p.y) {
+                screen.set(current_p.x as usize, current_p.y as usize, self.material.line_glyph(), self.material.border_color);
             }
             if (count_x as f32 * ratio.abs()) < count_y as f32 && current_p.x != p2.x {
                 current_p.x += x_increment;
@@ -113,26 +121,23 @@ fn lines_intersect(a: &Point2d, b: &Point2d, c: &Point2d, d: &Point2d) -> bool {
     return is_ccw(a, c, d) != is_ccw(b, c, d) && is_ccw(a, b, c) != is_ccw(a, b, d);
 }
 
-const CORNER1: Point2d = Point2d { x: 0, y: 0 };
-const CORNER2: Point2d = Point2d {
-    x: 0,
-    y: HEIGHT as i32,
-};
-const CORNER3: Point2d = Point2d {
-    x: WIDTH as i32,
-    y: 0,
-};
-const CORNER4: Point2d = Point2d {
-    x: WIDTH as i32,
-    y: HEIGHT as i32,
-};
+fn screen_corners(screen: &Screen) -> [Point2d; 4] {
+    let (width, height) = (screen.width as i32, screen.height as i32);
+    [
+        Point2d { x: 0, y: 0 },
+        Point2d { x: 0, y: height },
+        Point2d { x: width, y: 0 },
+        Point2d { x: width, y: height },
+    ]
+}
 
 impl Line2d {
-    pub fn is_in_screen(&self) -> bool {
-        return lines_intersect(&self.p1, &self.p2, &CORNER1, &CORNER2)
-            || lines_intersect(&self.p1, &self.p2, &CORNER1, &CORNER3)
-            || lines_intersect(&self.p1, &self.p2, &CORNER2, &CORNER4)
-            || lines_intersect(&self.p1, &self.p2, &CORNER3, &CORNER4);
+    pub fn is_in_screen(&self, screen: &Screen) -> bool {
+        let [corner1, corner2, corner3, corner4] = screen_corners(screen);
+        lines_intersect(&self.p1, &self.p2, &corner1, &corner2)
+            || lines_intersect(&self.p1, &self.p2, &corner1, &corner3)
+            || lines_intersect(&self.p1, &self.p2, &corner2, &corner4)
+            || lines_intersect(&self.p1, &self.p2, &corner3, &corner4)
     }
 
 }
@@ -140,10 +145,9 @@ impl Line2d {
 impl Line2dZ {
     pub fn add_to_grid(&self, screen: &mut Screen) {
         let (p1, p2) = (&self.p1.drop_z(), &self.p2.drop_z());
-        if !p1.is_in_screen() && !p2.is_in_screen() {
-            if (Line2d {p1: *p1, p2: *p2, character: self.character}).is_in_screen() {
-                return;
-            }
+        if !p1.is_in_screen(screen) && !p2.is_in_screen(screen)
+            && (Line2d {p1: *p1, p2: *p2, character: self.character}).is_in_screen(screen) {
+            return;
         }
         let mut current_p = Point2d { x: p1.x, y: p1.y };
         let diff_x = p2.x - current_p.x;
@@ -177,23 +181,13 @@ impl Line2dZ {
         let z_slope = (self.p2.z - self.p1.z) as f32 / count as f32;
         let mut z = self.p1.z as f32;
         while current_p.x != p2.x || current_p.y != p2.y {
-            if current_p.x >= 0
-                && current_p.x < WIDTH as i32
-                && current_p.y >= 0
-                && current_p.y < HEIGHT as i32
-            {
+            if screen.contains(current_p.x, current_p.y) {
                 let (x, y) = (current_p.x as usize, current_p.y as usize);
-                match screen.z_buf[y][x] {
-                    None => {
-                        screen.grid[y][x] = self.character;
+                if screen.z_buf[y][x].is_none_or(|pixel_z| z <= pixel_z + 5.0) {
+                    screen.set(x, y, self.character, self.color);
+                    if self.writes_depth {
                         screen.z_buf[y][x] = Some(z);
-                    },
-                    Some(pixel_z) => {
-                        if z <= pixel_z + 5.0 {
-                            screen.grid[y][x] = self.character;
-                            screen.z_buf[y][x] = Some(z);
-                        }
-                    },
+                    }
                 }
             }
             if (count_x as f32 * ratio.abs()) < count_y as f32 && current_p.x != p2.x {
@@ -209,3 +203,54 @@ impl Line2dZ {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::RenderMode;
+    use crate::testing::{close, view};
+
+    fn line(p1: Point3d, p2: Point3d) -> Line3d {
+        Line3d { p1, p2, material: Rc::new(Material::default()) }
+    }
+
+    #[test]
+    fn clip_near_keeps_a_line_in_front() {
+        let (p1, p2) = (Point3d { x: 0.0, y: 0.0, z: 10.0 }, Point3d { x: 5.0, y: 5.0, z: 50.0 });
+        let clipped = line(p1, p2).clip_near(&view()).unwrap();
+        assert!(close(&clipped.p1, &p1) && close(&clipped.p2, &p2));
+    }
+
+    #[test]
+    fn clip_near_cuts_the_end_behind() {
+        let (behind, ahead) = (Point3d { x: 0.0, y: 0.0, z: 0.0 }, Point3d { x: 20.0, y: -40.0, z: 40.0 });
+        let cut = Point3d { x: 5.0, y: -10.0, z: 10.0 };
+
+        let clipped = line(behind, ahead).clip_near(&view()).unwrap();
+        assert!(close(&clipped.p1, &cut) && close(&clipped.p2, &ahead));
+
+        // the order of the points is kept
+        let clipped = line(ahead, behind).clip_near(&view()).unwrap();
+        assert!(close(&clipped.p1, &ahead) && close(&clipped.p2, &cut));
+    }
+
+    #[test]
+    fn transparent_lines_leave_the_depth() {
+        let mut screen = Screen::new(10, 5, RenderMode::Ascii);
+        let color = CellColor::default();
+        let (p1, p2) = (Point2dZ { x: 1, y: 2, z: 50 }, Point2dZ { x: 8, y: 2, z: 50 });
+        Line2dZ { p1, p2, character: b'-', color, writes_depth: false }.add_to_grid(&mut screen);
+        assert_eq!(screen.grid[2][4], b'-');
+        assert!(screen.z_buf[2].iter().all(|z| z.is_none()));
+
+        Line2dZ { p1, p2, character: b'=', color, writes_depth: true }.add_to_grid(&mut screen);
+        assert_eq!(screen.grid[2][4], b'=');
+        assert_eq!(screen.z_buf[2][4], Some(50.0));
+    }
+
+    #[test]
+    fn clip_near_drops_a_line_behind() {
+        let l = line(Point3d { x: 0.0, y: 0.0, z: 9.0 }, Point3d { x: 100.0, y: 0.0, z: -100.0 });
+        assert!(l.clip_near(&view()).is_none());
+    }
+}
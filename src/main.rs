@@ -1,38 +1,84 @@
 use crate::constants::{FPS, HEIGHT, WIDTH};
-use crate::point::{distance, Point3d};
+use crate::point::{distance, Point2d, Point3d};
 use crate::triangle::Triangle3d;
-use crate::projection::{Camera, Shape3d};
+use crate::projection::{Camera, Projection};
 use crate::procedural::generate_shape_height_map;
+use crate::color::{CellColor, ColorMode, Rgb};
+use crate::display::{Pixel, RenderMode, Shape2d};
+use crate::backend::{FileBackend, MemoryBackend, NullBackend, RenderBackend, TerminalBackend};
+use crate::overlay::Overlay;
+use crate::shape2d::{Ellipse, FilledRect, Polygon, Polyline, Rect, Text};
+use crate::light::Light;
+use crate::render::RenderSettings;
+use crate::fog::{Fog, FogMode};
+use crate::math::{Mat3, Mat4, Vec3};
 
 use std::f32::consts::PI;
 
 use device_query::{DeviceQuery, DeviceState, Keycode};
 
+mod backend;
+mod color;
 mod constants;
 mod display;
 mod file;
+mod fog;
+mod light;
 mod line;
+mod material;
+mod math;
 mod point;
 mod terminal;
+mod texture;
 mod triangle;
 mod projection;
+mod overlay;
 mod procedural;
+mod render;
+mod shadow;
+mod shape2d;
+mod snapshot;
 mod triangulate;
+#[cfg(test)]
+mod testing;
 
 fn main() {
+    // --record <file> writes the frames as text, --null drops them for
+    // benchmarks, --scene <file> shows a .dat file instead of the generated
+    // terrain, --camera x,y,z,pitch,yaw,roll starts at a position and angles
+    // in degrees as the HUD shows them, --view top|front|side starts in an
+    // orthographic view that fits the whole scene, --frames <n> stops after
+    // that many frames, --snapshot <file> checks the last frame against a
+    // golden file, see snapshot.rs, drawing one frame unless --frames is given
+    // and --scale <factor> sizes the scene around its middle
+    let mut record: Option<String> = None;
+    let mut null = false;
+    let mut scene: Option<String> = None;
+    let mut start: Option<String> = None;
+    let mut view: Option<String> = None;
+    let mut frame_limit: Option<usize> = None;
+    let mut golden: Option<String> = None;
+    let mut scale = 1.0;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => record = Some(args.next().expect("--record needs a file path")),
+            "--null" => null = true,
+            "--scene" => scene = Some(args.next().expect("--scene needs a .dat file")),
+            "--camera" => start = Some(args.next().expect("--camera needs x,y,z,pitch,yaw,roll")),
+            "--view" => view = Some(args.next().expect("--view needs top, front or side")),
+            "--snapshot" => golden = Some(args.next().expect("--snapshot needs a golden file")),
+            "--scale" => scale = args.next().and_then(|n| n.parse::<f32>().ok()).expect("--scale needs a number"),
+            "--frames" => frame_limit = Some(args.next().and_then(|n| n.parse().ok()).expect("--frames needs a number")),
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
 
-    let mut camera = Camera {
-        pos: Point3d {
-            x: 0.0,
-            y: 300.0,
-            z: 0.0,
-        },
-        rot: Point3d {
-            x: 0.0,
-            y: 0.0,
-            z: 0.0,
-        },
-    };
+    let mut camera = Camera::new(Point3d {
+        x: 0.0,
+        y: 300.0,
+        z: 0.0,
+    });
 
 
     //REFERENCE POINTS
@@ -41,23 +87,109 @@ fn main() {
     let ref_p3 = Point3d { x: -500.0, y: 0.0, z: 500.0, };
     let ref_p4 = Point3d { x: 300.0, y: 400.0, z: 0.0, };
     
-    let mut terrain = generate_shape_height_map(b'-');
+    let terrain = match &scene {
+        Some(path) => file::read_data_file(path).unwrap_or_else(|error| panic!("{}", error)),
+        None => generate_shape_height_map(b'-'),
+    };
+    // the model matrix turns and sizes the scene around the middle of its
+    // bounds, [ and ] turn it
+    let middle = projection::bounds(&terrain).map(|(min, max)| min.lerp(&max, 0.5)).unwrap_or(point::ORIGIN);
+    let mut spin = 0.0;
+    if let Some(start) = start {
+        let values: Vec<f32> = start.split(',').map(|v| v.trim().parse::<f32>().expect("Bad number in --camera")).collect();
+        if values.len() != 6 {
+            panic!("--camera needs x,y,z,pitch,yaw,roll");
+        }
+        let pos = Point3d { x: values[0], y: values[1], z: values[2] };
+        camera = Camera::from_rotation_degrees(pos, &Point3d { x: values[3], y: values[4], z: values[5] });
+    }
+    if let Some(view) = view {
+        let (min, max) = projection::bounds(&terrain).expect("Nothing to view");
+        let center = Point3d { x: (min.x + max.x) / 2.0, y: (min.y + max.y) / 2.0, z: (min.z + max.z) / 2.0 };
+        let size = (max.x - min.x).max(max.y - min.y).max(max.z - min.z) * 1.1 * scale;
+        camera = match view.as_str() {
+            "top" => Camera::top_view(&center, size),
+            "front" => Camera::front_view(&center, size),
+            "side" => Camera::side_view(&center, size),
+            _ => panic!("--view needs top, front or side"),
+        };
+    }
+    // R glides back here over a second, from wherever the camera is then
+    let home = camera;
+    let mut glide: Option<(Camera, usize)> = None;
+    let lights = vec![
+        Light::Ambient { intensity: 0.15 },
+        Light::Directional { direction: Point3d { x: 0.4, y: -1.0, z: 0.6 }, intensity: 0.6 },
+        Light::Point { position: Point3d { x: 1500.0, y: 300.0, z: 1500.0 }, intensity: 0.8, falloff: 0.000005 },
+        Light::Spot {
+            position: Point3d { x: 3000.0, y: 800.0, z: 3000.0 },
+            direction: Point3d { x: 0.0, y: -1.0, z: 0.0 },
+            intensity: 1.0,
+            falloff: 0.000001,
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+        },
+    ];
+    let mut lighting_on = true;
+    let mut shadows_on = false;
+    // F cycles through no fog, linear and exponential
+    let fog_color = Rgb::new(60, 60, 70);
+    let fogs = [
+        None,
+        Some(Fog { mode: FogMode::Linear { start: 1500.0, end: 4000.0 }, glyph: b' ', color: fog_color }),
+        Some(Fog { mode: FogMode::Exponential { start: 1000.0, density: 0.0008 }, glyph: b' ', color: fog_color }),
+    ];
+    let mut fog_index = 0;
 
     let delay: u64 = (1000 / FPS) as u64;
     let mut move_per_frame = 100.0 / FPS as f32;
     let radians_per_frame = PI / 1.5 / FPS as f32;
-    let device_state = DeviceState::new();
-    let mut keys: Vec<Keycode> = device_state.get_keys();
-    let mut last_keys: Vec<Keycode> = device_state.get_keys();
+    // + and - narrow and widen the view, by about 2.7 times a second
+    let zoom_per_frame = 1.0 + 1.0 / FPS as f32;
     let mut real_fps = 0.0;
 
+    let headless = record.is_some() || null || golden.is_some();
+    if golden.is_some() && frame_limit.is_none() {
+        frame_limit = Some(1);
+    }
+    // snapshot runs keep their frames to look at the last one afterwards
+    let mut memory = MemoryBackend::new();
+    let mut output: Box<dyn RenderBackend> = match record {
+        Some(path) => Box::new(FileBackend::new(&path).expect("Unable to create file")),
+        None if null => Box::new(NullBackend::new()),
+        None => Box::new(TerminalBackend::new(ColorMode::detect())),
+    };
+    let backend: &mut dyn RenderBackend = if golden.is_some() { &mut memory } else { output.as_mut() };
+    // the keyboard and the terminal are only taken over when playing in it,
+    // headless runs have no input and keep a fixed size so recordings match
+    let device_state = if headless { None } else { Some(DeviceState::new()) };
+    let session = if headless { None } else { Some(terminal::Session::start().expect("stdin has to be a terminal")) };
+    let get_keys = || device_state.as_ref().map(|d| d.get_keys()).unwrap_or_default();
+    let mut keys: Vec<Keycode> = get_keys();
+    let mut last_keys: Vec<Keycode> = get_keys();
+    if !headless {
+        terminal::watch_resize();
+    }
+
+    let screen_size = || if headless { (WIDTH, HEIGHT) } else { terminal::get_size().unwrap_or((WIDTH, HEIGHT)) };
+    let mut render_mode = RenderMode::Ascii;
+    let (cols, rows) = screen_size();
+    let mut screen = display::Screen::new(cols, rows, render_mode);
+    let mut hud = Overlay::new();
+    let hud_color = CellColor { fg: Some(Rgb::new(255, 255, 255)), bg: Some(Rgb::new(20, 20, 40)) };
+
+    let run_start = std::time::Instant::now();
+    let mut frame_count: usize = 0;
     'game_loop: loop {
+        if terminal::quit_requested() || frame_limit.is_some_and(|limit| frame_count >= limit) {
+            break 'game_loop;
+        }
         let start_frame_time = std::time::SystemTime::now();
         last_keys.clear();
         for key in &keys {
             last_keys.push(*key);
         }
-        keys = device_state.get_keys();
+        keys = get_keys();
 
         //CONTROLS
         if keys.contains(&Keycode::LShift) && !last_keys.contains(&Keycode::LShift) {
@@ -66,72 +198,128 @@ fn main() {
         if !keys.contains(&Keycode::LShift) && last_keys.contains(&Keycode::LShift) {
             move_per_frame = 100.0 / FPS as f32;
         }
-        camera.rot.y = 0.0; camera.rot.z = 0.0;
+        if keys.contains(&Keycode::M) && !last_keys.contains(&Keycode::M) {
+            render_mode = render_mode.next();
+        }
+        if keys.contains(&Keycode::I) && !last_keys.contains(&Keycode::I) {
+            lighting_on = !lighting_on;
+        }
+        if keys.contains(&Keycode::O) && !last_keys.contains(&Keycode::O) {
+            shadows_on = !shadows_on;
+        }
+        if keys.contains(&Keycode::P) && !last_keys.contains(&Keycode::P) {
+            camera.projection = match camera.projection {
+                Projection::Perspective => Projection::Orthographic,
+                Projection::Orthographic => Projection::Perspective,
+            };
+        }
+        if keys.contains(&Keycode::F) && !last_keys.contains(&Keycode::F) {
+            fog_index = (fog_index + 1) % fogs.len();
+        }
+        // walking stays level, whatever the pitch and roll of the camera
+        let forward = camera.forward();
+        let flat = Point3d { x: forward.x, y: 0.0, z: forward.z }.normalized();
+        let right = Point3d { x: flat.z, y: 0.0, z: -flat.x };
+        let step = |pos: &mut Point3d, toward: &Point3d, amount: f32| {
+            pos.x += toward.x * amount;
+            pos.z += toward.z * amount;
+        };
         for key in &keys {
             match key {
                 Keycode::X  => camera.pos.y += move_per_frame,
                 Keycode::Z  => camera.pos.y -= move_per_frame,
 
-                // Keycode::Left  => camera.rot.z += radians_per_frame,
-                // Keycode::Right  => camera.rot.z -= radians_per_frame,
-                // Keycode::Up  => camera.rot.x -= radians_per_frame,
-                // Keycode::Down  => camera.rot.x += radians_per_frame,
+                Keycode::H | Keycode::Left => camera.yaw(-radians_per_frame),
+                Keycode::L | Keycode::Right => camera.yaw(radians_per_frame),
+                Keycode::K | Keycode::Up => camera.pitch(radians_per_frame / 2.0),
+                Keycode::J | Keycode::Down => camera.pitch(-radians_per_frame / 2.0),
+                Keycode::Comma => camera.roll(radians_per_frame / 2.0),
+                Keycode::Dot => camera.roll(-radians_per_frame / 2.0),
+                Keycode::LeftBracket => spin -= radians_per_frame,
+                Keycode::RightBracket => spin += radians_per_frame,
+                Keycode::Equal => camera.zoom(zoom_per_frame),
+                Keycode::Minus => camera.zoom(1.0 / zoom_per_frame),
 
-                Keycode::H | Keycode::Left => camera.rot.y += radians_per_frame,
-                Keycode::L | Keycode::Right => camera.rot.y -= radians_per_frame,
-                Keycode::K | Keycode::Up => camera.rot.x += radians_per_frame / 2.0,
-                Keycode::J | Keycode::Down => camera.rot.x -= radians_per_frame / 2.0,
+                Keycode::A  => step(&mut camera.pos, &right, -move_per_frame),
+                Keycode::D  => step(&mut camera.pos, &right, move_per_frame),
+                Keycode::W  => step(&mut camera.pos, &flat, move_per_frame),
+                Keycode::S  => step(&mut camera.pos, &flat, -move_per_frame),
 
-                Keycode::A  => camera.pos.x -= move_per_frame,
-                Keycode::D  => camera.pos.x += move_per_frame,
-                Keycode::W  => camera.pos.z += move_per_frame,
-                Keycode::S  => camera.pos.z -= move_per_frame,
-
-                Keycode::Space => camera.pos.z += move_per_frame,
+                Keycode::Space => step(&mut camera.pos, &flat, move_per_frame),
 
                 Keycode::Q => break 'game_loop,
                 _ => (),
             }
         }
+        if keys.contains(&Keycode::R) && !last_keys.contains(&Keycode::R) {
+            glide = Some((camera, 0));
+        }
+        if let Some((from, frame)) = glide {
+            let t = (frame + 1) as f32 / FPS as f32;
+            camera = from.slerp(&home, t.min(1.0));
+            glide = if t < 1.0 { Some((from, frame + 1)) } else { None };
+        }
 
-        let mut screen = display::Screen {
-            grid: [[b' '; WIDTH]; HEIGHT],
-            z_buf: [[None; WIDTH]; HEIGHT],
+        let settings = RenderSettings {
+            lights: if lighting_on { lights.clone() } else { vec![] },
+            shadows: shadows_on,
+            fog: fogs[fog_index],
         };
-
-        let mut render_shapes = |shape_vec: &mut Vec<Shape3d>| {
-            for s in shape_vec {
-                match s {
-                    Shape3d::Triangle(t) => {
-                        t.rotate_y_mut(&camera.pos, camera.rot.y);
-                        t.rotate_z_mut(&camera.pos, camera.rot.z);
-                        let t_2d = t.project(&Camera {
-                            pos: camera.pos,
-                            rot: Point3d {x:0.0, y:camera.rot.x, z:0.0}
-                        });
-                        // println!("({}, {}, {})", t_2d.points[0], t_2d.points[1], t_2d.points[2]);
-                        t_2d.add_to_grid(&mut screen);
-                        t_2d.add_border_to_grid(&mut screen);
-                    },
-                    Shape3d::Line(l) => {
-                        l.rotate_y_mut(&camera.pos, camera.rot.y);
-                        l.rotate_z_mut(&camera.pos, camera.rot.z);
-                        let l_2d = l.project(&Camera {
-                            pos: camera.pos,
-                            rot: Point3d {x:0.0, y:camera.rot.x, z:0.0}
-                        });
-                        l_2d.add_to_grid(&mut screen);
-                    },
-                }
+        let back = Vec3 { x: -middle.x, y: -middle.y, z: -middle.z };
+        let model = Mat4::translation(&middle) * Mat4::rotation_y(spin) * Mat4::scale(scale, scale, scale) * Mat4::translation(&back);
+        if headless {
+            // always the same size, nothing is kept from the last frame
+            screen = render::render_offscreen(&terrain, &model, &camera, &settings, WIDTH, HEIGHT, render_mode);
+        } else {
+            if terminal::was_resized() || render_mode != screen.mode {
+                let (cols, rows) = screen_size();
+                screen.resize(cols, rows, render_mode);
             }
+            screen.clear();
+            render::render_shapes(&terrain, &model, &camera, &settings, &mut screen);
+        }
+
+        //CROSSHAIR
+        let (center_x, center_y) = (screen.width as i32 / 2, screen.height as i32 / 2);
+        let (arm_x, arm_y) = (screen.width as i32 / 80 + 1, screen.height as i32 / 48 + 1);
+        let crosshair_color = CellColor { fg: Some(Rgb::new(255, 80, 80)), bg: None };
+        Polyline {
+            points: vec![Point2d {x: center_x - arm_x, y: center_y}, Point2d {x: center_x + arm_x, y: center_y}],
+            pixel: Pixel { character: b'-', z: None, color: crosshair_color },
+        }.add_to_grid(&mut screen);
+        Polyline {
+            points: vec![Point2d {x: center_x, y: center_y - arm_y}, Point2d {x: center_x, y: center_y + arm_y}],
+            pixel: Pixel { character: b'|', z: None, color: crosshair_color },
+        }.add_to_grid(&mut screen);
+
+        //COMPASS
+        // bottom right, the needle points where the camera looks with +z up.
+        // The x radius makes up for pixels that aren't square.
+        let (cell_width, cell_height) = screen.mode.cell_size();
+        let stretch = camera.cell_aspect * cell_width as f32 / cell_height as f32;
+        let radius_y = screen.height as i32 / 12 + 2;
+        let radius_x = (radius_y as f32 * stretch) as i32;
+        let compass = Point2d { x: screen.width as i32 - radius_x - 4, y: radius_y + 2 };
+        let compass_pixel = Pixel { character: b'.', z: None, color: hud_color };
+        let (corner_1, corner_2) = (
+            Point2d { x: compass.x - radius_x - 2, y: compass.y - radius_y - 1 },
+            Point2d { x: compass.x + radius_x + 2, y: compass.y + radius_y + 1 },
+        );
+        FilledRect { p1: corner_1, p2: corner_2, pixel: Pixel { character: b' ', ..compass_pixel } }.add_to_grid(&mut screen);
+        Rect { p1: corner_1, p2: corner_2, pixel: Pixel { character: b'+', ..compass_pixel } }.add_to_grid(&mut screen);
+        Ellipse { center: compass, radius_x, radius_y, filled: false, pixel: compass_pixel }.add_to_grid(&mut screen);
+        let turn = Mat3::rotation_z(-camera.euler().y);
+        let at = |along: f32, across: f32| {
+            let v = turn.transform(&Vec3 { x: across, y: along, z: 0.0 });
+            Point2d { x: compass.x + (v.x * radius_y as f32 * stretch) as i32, y: compass.y + (v.y * radius_y as f32) as i32 }
         };
-        render_shapes(&mut terrain);
-        display::print_grid(&screen);
+        Polygon {
+            points: vec![at(0.8, 0.0), at(-0.3, 0.25), at(-0.3, -0.25)],
+            filled: true,
+            pixel: Pixel { character: b'#', z: None, color: crosshair_color },
+        }.add_to_grid(&mut screen);
+        Text { pos: Point2d { x: compass.x, y: compass.y + radius_y }, text: String::from("N"), pixel: compass_pixel }.add_to_grid(&mut screen);
 
-        for ref_p in &mut [ref_p1, ref_p2, ref_p3, ref_p4] {
-            ref_p.rotate_y_mut(&camera.pos, camera.rot.y);
-            ref_p.rotate_z_mut(&camera.pos, camera.rot.z);
-        }
         //DISTANCE CALCULATIONS
         let d1 = distance(&camera.pos, &ref_p1);
         let d2 = distance(&camera.pos, &ref_p2);
@@ -139,17 +327,48 @@ fn main() {
         let d4 = distance(&camera.pos, &ref_p4);
         let real_location = triangulate::triangulate(ref_p1, ref_p2, ref_p3, ref_p4, d1, d2, d3, d4);
 
-        println!("REAL: {}", real_location);
-        println!("{}", camera.pos);
-        println!("{}", camera.rotation_degrees());
-        println!("FPS: {}", real_fps);
-
         let time_processing = std::time::SystemTime::now().duration_since(start_frame_time).unwrap().as_millis();
-        println!("PROCESS: {}", time_processing);
-        if (time_processing as u64) < delay {
+        hud.clear();
+        let mut status = vec![
+            format!("REAL: {}", real_location),
+            format!("{}", camera.pos),
+            format!("{}", camera.rotation_degrees()),
+            match camera.projection {
+                Projection::Perspective => format!("FOV: {:.0}", camera.fov.to_degrees()),
+                Projection::Orthographic => format!("ORTHO: {:.0}", camera.ortho_height),
+            },
+        ];
+        // timings differ from run to run, headless frames have to be repeatable
+        if !headless {
+            status.push(format!("FPS: {:.1}", real_fps));
+            status.push(format!("PROCESS: {}", time_processing));
+        }
+        hud.add_panel(1, 0, "termgl", status, hud_color);
+        // the keys, along the bottom
+        let help = "WASD move  HJKL look  ,. roll  +- zoom  M mode  I light  O shadows  F fog  P projection  [] turn  R home  Q quit";
+        let help_row = screen.rows.saturating_sub(3);
+        hud.add_box(1, help_row, help.len() + 4, 3, hud_color);
+        hud.add_text(3, help_row + 1, help, hud_color);
+        hud.draw(&mut screen);
+        backend.present(&screen);
+        frame_count += 1;
+        // headless runs go as fast as they can
+        if !headless && (time_processing as u64) < delay {
             std::thread::sleep(std::time::Duration::from_millis(delay - time_processing as u64));
         }
         let total_time = std::time::SystemTime::now().duration_since(start_frame_time).unwrap().as_millis();
         real_fps = 1000.0 / total_time as f64;
     }
+
+    // after the terminal is given back, or the line would be lost
+    drop(session);
+    let elapsed = run_start.elapsed().as_secs_f64();
+    println!("{} frames in {:.2}s, {:.1} fps", frame_count, elapsed, frame_count as f64 / elapsed);
+    if let Some(path) = golden {
+        let last = memory.frames.last().expect("No frame was drawn");
+        if let Err(error) = snapshot::check_golden(last, &path) {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,46 @@
+use std::rc::Rc;
+
+use crate::color::CellColor;
+use crate::light::Shading;
+use crate::texture::Texture;
+use crate::triangle::Culling;
+
+// How a shape looks. Shapes hold it behind an Rc, so all the shapes of a .dat
+// file that name the same material share one and are restyled together.
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    pub fill: u8,
+    // edges of triangles, and the glyph of lines
+    pub border: Option<u8>,
+    pub color: CellColor,
+    pub border_color: CellColor,
+    pub shading: Shading,
+    pub culling: Culling,
+    // 0.0 is opaque, 1.0 invisible
+    pub transparency: f32,
+    pub texture: Option<Rc<Texture>>,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material {
+            name: String::new(),
+            fill: b'#',
+            border: None,
+            color: CellColor::default(),
+            border_color: CellColor::default(),
+            shading: Shading::None,
+            culling: Culling::None,
+            transparency: 0.0,
+            texture: None,
+        }
+    }
+}
+
+impl Material {
+    // lines are all edge, they only fall back to the fill without a border
+    pub fn line_glyph(&self) -> u8 {
+        self.border.unwrap_or(self.fill)
+    }
+}
@@ -0,0 +1,328 @@
+use std::ops::Mul;
+
+use num_quaternion::UQ32;
+
+use crate::point::Point3d;
+
+// Points and directions are both Point3d, a direction just leaves out the
+// translation of a matrix
+pub type Vec3 = Point3d;
+
+// Row major, a matrix times a column vector. The rotations turn by `rads`
+// counterclockwise looking down the axis toward the origin.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Mat3 {
+    pub m: [[f32; 3]; 3],
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Mat4 {
+    pub m: [[f32; 4]; 4],
+}
+
+impl Mat3 {
+    pub const IDENTITY: Mat3 = Mat3 { m: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]] };
+
+    pub fn rotation_y(rads: f32) -> Mat3 {
+        let (s, c) = rads.sin_cos();
+        Mat3 { m: [[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]] }
+    }
+    pub fn rotation_z(rads: f32) -> Mat3 {
+        let (s, c) = rads.sin_cos();
+        Mat3 { m: [[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]] }
+    }
+    pub fn from_rotation(rotation: &UQ32) -> Mat3 {
+        let r = rotation.to_rotation_matrix3x3();
+        Mat3 { m: [[r[0], r[1], r[2]], [r[3], r[4], r[5]], [r[6], r[7], r[8]]] }
+    }
+    pub fn scale(x: f32, y: f32, z: f32) -> Mat3 {
+        Mat3 { m: [[x, 0.0, 0.0], [0.0, y, 0.0], [0.0, 0.0, z]] }
+    }
+
+    pub fn transpose(&self) -> Mat3 {
+        let mut t = Mat3::IDENTITY;
+        for r in 0..3 {
+            for c in 0..3 {
+                t.m[r][c] = self.m[c][r];
+            }
+        }
+        t
+    }
+
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    // None when the matrix flattens space and can't be undone
+    pub fn inverse(&self) -> Option<Mat3> {
+        let determinant = self.determinant();
+        if determinant.abs() < f32::EPSILON {
+            return None;
+        }
+        let m = &self.m;
+        let cofactor = |r1: usize, c1: usize, r2: usize, c2: usize| m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1];
+        // the adjugate, already transposed
+        let adjugate = [
+            [cofactor(1, 1, 2, 2), -cofactor(0, 1, 2, 2), cofactor(0, 1, 1, 2)],
+            [-cofactor(1, 0, 2, 2), cofactor(0, 0, 2, 2), -cofactor(0, 0, 1, 2)],
+            [cofactor(1, 0, 2, 1), -cofactor(0, 0, 2, 1), cofactor(0, 0, 1, 1)],
+        ];
+        Some(Mat3 { m: adjugate.map(|row| row.map(|v| v / determinant)) })
+    }
+
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3 {
+            x: m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            y: m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            z: m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        }
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Mat4 = Mat4 {
+        m: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+    };
+
+    pub fn from_mat3(linear: &Mat3) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        for r in 0..3 {
+            for c in 0..3 {
+                m.m[r][c] = linear.m[r][c];
+            }
+        }
+        m
+    }
+    pub fn translation(offset: &Vec3) -> Mat4 {
+        let mut m = Mat4::IDENTITY;
+        m.m[0][3] = offset.x;
+        m.m[1][3] = offset.y;
+        m.m[2][3] = offset.z;
+        m
+    }
+    pub fn rotation_y(rads: f32) -> Mat4 {
+        Mat4::from_mat3(&Mat3::rotation_y(rads))
+    }
+    pub fn scale(x: f32, y: f32, z: f32) -> Mat4 {
+        Mat4::from_mat3(&Mat3::scale(x, y, z))
+    }
+    // From view space, looking along +z, to the pixels of a `width` by
+    // `height` screen, `fov` is vertical and `aspect` the width over height
+    // of the screen as it's seen. w ends up as the depth, and z is kept as it
+    // is for the depth buffer instead of being mapped between near and far.
+    pub fn perspective(fov: f32, aspect: f32, width: f32, height: f32) -> Mat4 {
+        let tan = (fov / 2.0).tan();
+        let (center_x, center_y) = ((width / 2.0).floor(), (height / 2.0).floor());
+        let focal_y = height / 2.0 / tan;
+        let focal_x = width / 2.0 / (aspect * tan);
+        Mat4 {
+            m: [
+                [focal_x, 0.0, center_x, 0.0],
+                [0.0, focal_y, center_y, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+        }
+    }
+
+    // Like perspective, with `view_height` world units across the screen's
+    // height at any depth. w stays 1.
+    pub fn orthographic(view_height: f32, aspect: f32, width: f32, height: f32) -> Mat4 {
+        let (center_x, center_y) = ((width / 2.0).floor(), (height / 2.0).floor());
+        let scale_y = height / view_height;
+        let scale_x = width / (aspect * view_height);
+        Mat4 {
+            m: [
+                [scale_x, 0.0, 0.0, center_x],
+                [0.0, scale_y, 0.0, center_y],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    // the rotation and scale, without the translation
+    pub fn linear(&self) -> Mat3 {
+        let mut linear = Mat3::IDENTITY;
+        for r in 0..3 {
+            for c in 0..3 {
+                linear.m[r][c] = self.m[r][c];
+            }
+        }
+        linear
+    }
+
+    // Gauss-Jordan elimination, None when the matrix can't be undone
+    pub fn inverse(&self) -> Option<Mat4> {
+        let mut m = self.m;
+        let mut inverse = Mat4::IDENTITY.m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|a, b| m[*a][col].abs().total_cmp(&m[*b][col].abs())).unwrap();
+            if m[pivot][col].abs() < f32::EPSILON {
+                return None;
+            }
+            m.swap(col, pivot);
+            inverse.swap(col, pivot);
+            let scale = m[col][col];
+            for c in 0..4 {
+                m[col][c] /= scale;
+                inverse[col][c] /= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = m[row][col];
+                for c in 0..4 {
+                    m[row][c] -= factor * m[col][c];
+                    inverse[row][c] -= factor * inverse[col][c];
+                }
+            }
+        }
+        Some(Mat4 { m: inverse })
+    }
+
+    // x, y, z and w before the perspective divide
+    pub fn transform_homogeneous(&self, p: &Vec3) -> [f32; 4] {
+        let m = &self.m;
+        [0, 1, 2, 3].map(|r| m[r][0] * p.x + m[r][1] * p.y + m[r][2] * p.z + m[r][3])
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let [x, y, z, w] = self.transform_homogeneous(p);
+        if w == 1.0 || w == 0.0 {
+            return Vec3 { x, y, z };
+        }
+        Vec3 { x: x / w, y: y / w, z: z / w }
+    }
+
+    // Normals need the inverse transpose to stay perpendicular to their face
+    // under uneven scaling
+    pub fn normal_matrix(&self) -> Mat3 {
+        self.linear().inverse().map(|i| i.transpose()).unwrap_or(Mat3::IDENTITY)
+    }
+}
+
+// A model matrix along with its normal matrix, so the inverse is worked out
+// once for all the points and normals it moves instead of once per normal
+pub struct Transform {
+    pub matrix: Mat4,
+    pub normal_matrix: Mat3,
+    // true when the matrix mirrors, which turns the winding of triangles over
+    pub mirrors: bool,
+}
+
+impl Transform {
+    pub fn new(matrix: &Mat4) -> Transform {
+        Transform {
+            matrix: *matrix,
+            normal_matrix: matrix.normal_matrix(),
+            mirrors: matrix.linear().determinant() < 0.0,
+        }
+    }
+
+    pub fn point(&self, p: &Vec3) -> Vec3 {
+        self.matrix.transform_point(p)
+    }
+
+    // comes back normalized
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        self.normal_matrix.transform(n).normalized()
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+    fn mul(self, other: Mat3) -> Mat3 {
+        let mut product = Mat3 { m: [[0.0; 3]; 3] };
+        for r in 0..3 {
+            for c in 0..3 {
+                product.m[r][c] = (0..3).map(|i| self.m[r][i] * other.m[i][c]).sum();
+            }
+        }
+        product
+    }
+}
+
+// a * b applies b first, then a
+impl Mul for Mat4 {
+    type Output = Mat4;
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut product = Mat4 { m: [[0.0; 4]; 4] };
+        for r in 0..4 {
+            for c in 0..4 {
+                product.m[r][c] = (0..4).map(|i| self.m[r][i] * other.m[i][c]).sum();
+            }
+        }
+        product
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::close;
+    use std::f32::consts::PI;
+
+    fn close_mat4(a: &Mat4, b: &Mat4) -> bool {
+        (0..4).all(|r| (0..4).all(|c| (a.m[r][c] - b.m[r][c]).abs() < 1e-5))
+    }
+
+    const X: Vec3 = Vec3 { x: 1.0, y: 0.0, z: 0.0 };
+    const Y: Vec3 = Vec3 { x: 0.0, y: 1.0, z: 0.0 };
+    const Z: Vec3 = Vec3 { x: 0.0, y: 0.0, z: 1.0 };
+
+    // counterclockwise looking down each axis toward the origin: y turns z
+    // into x and z turns x into y. The old rotate_z of Point3d went the other
+    // way.
+    #[test]
+    fn rotations_turn_counterclockwise() {
+        assert!(close(&Mat3::rotation_y(PI / 2.0).transform(&Z), &X));
+        assert!(close(&Mat3::rotation_z(PI / 2.0).transform(&X), &Y));
+        assert!(close(&Mat4::rotation_y(PI / 2.0).transform_point(&Z), &X));
+    }
+
+    #[test]
+    fn product_applies_the_right_matrix_first() {
+        let (turn, offset) = (Mat4::from_mat3(&Mat3::rotation_z(PI / 2.0)), Vec3 { x: 10.0, y: 0.0, z: 0.0 });
+        let move_then_turn = turn * Mat4::translation(&offset);
+        let turn_then_move = Mat4::translation(&offset) * turn;
+        assert!(close(&move_then_turn.transform_point(&X), &Vec3 { x: 0.0, y: 11.0, z: 0.0 }));
+        assert!(close(&turn_then_move.transform_point(&X), &Vec3 { x: 10.0, y: 1.0, z: 0.0 }));
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = Mat4::translation(&Vec3 { x: 5.0, y: -3.0, z: 8.0 }) * Mat4::rotation_y(0.7) * Mat4::from_mat3(&Mat3::rotation_z(-0.4)) * Mat4::scale(2.0, 0.5, 3.0);
+        let inverse = m.inverse().unwrap();
+        assert!(close_mat4(&(m * inverse), &Mat4::IDENTITY));
+        assert!(close_mat4(&(inverse * m), &Mat4::IDENTITY));
+
+        let linear = m.linear();
+        let product = linear * linear.inverse().unwrap();
+        assert!((0..3).all(|r| (0..3).all(|c| (product.m[r][c] - Mat3::IDENTITY.m[r][c]).abs() < 1e-5)));
+    }
+
+    #[test]
+    fn flat_matrices_have_no_inverse() {
+        assert!(Mat4::scale(1.0, 0.0, 1.0).inverse().is_none());
+        assert!(Mat3::scale(1.0, 1.0, 0.0).inverse().is_none());
+    }
+
+    // squashing a slope along x tilts its normal the other way, toward x
+    #[test]
+    fn normals_stay_perpendicular() {
+        let transform = Transform::new(&Mat4::scale(0.5, 1.0, 1.0));
+        let (edge, normal) = (Vec3 { x: 1.0, y: 1.0, z: 0.0 }, Vec3 { x: 1.0, y: -1.0, z: 0.0 });
+        let moved_edge = transform.point(&edge);
+        let moved_normal = transform.normal(&normal);
+        assert!(moved_edge.dot(&moved_normal).abs() < 1e-6);
+        assert!((moved_normal.dot(&moved_normal) - 1.0).abs() < 1e-6);
+        assert!(!transform.mirrors);
+        assert!(Transform::new(&Mat4::scale(-1.0, 1.0, 1.0)).mirrors);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::color::CellColor;
+use crate::display::Screen;
+
+// 2D elements drawn over the finished 3D frame, in terminal cells with
+// (0, 0) at the top left. They never read or write the depth buffer.
+pub enum OverlayItem {
+    Text {
+        col: usize,
+        row: usize,
+        text: String,
+        color: CellColor,
+    },
+    // outline only, the inside keeps showing the 3D frame
+    Box {
+        col: usize,
+        row: usize,
+        width: usize,
+        height: usize,
+        color: CellColor,
+    },
+    // a box with a title, filled so the lines inside stay readable, sized to
+    // fit the longest line
+    Panel {
+        col: usize,
+        row: usize,
+        title: String,
+        lines: Vec<String>,
+        color: CellColor,
+    },
+}
+
+pub struct Overlay {
+    pub items: Vec<OverlayItem>,
+}
+
+impl Overlay {
+    pub fn new() -> Overlay {
+        Overlay { items: vec![] }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn add_text(&mut self, col: usize, row: usize, text: &str, color: CellColor) {
+        self.items.push(OverlayItem::Text { col, row, text: text.to_string(), color });
+    }
+
+    pub fn add_box(&mut self, col: usize, row: usize, width: usize, height: usize, color: CellColor) {
+        self.items.push(OverlayItem::Box { col, row, width, height, color });
+    }
+
+    pub fn add_panel(&mut self, col: usize, row: usize, title: &str, lines: Vec<String>, color: CellColor) {
+        self.items.push(OverlayItem::Panel { col, row, title: title.to_string(), lines, color });
+    }
+
+    // Composites the items onto the screen in the order they were added
+    pub fn draw(&self, screen: &mut Screen) {
+        for item in &self.items {
+            match item {
+                OverlayItem::Text { col, row, text, color } => {
+                    draw_text(screen, *col, *row, text, *color);
+                },
+                OverlayItem::Box { col, row, width, height, color } => {
+                    draw_box(screen, *col, *row, *width, *height, *color);
+                },
+                OverlayItem::Panel { col, row, title, lines, color } => {
+                    let inner_width = lines.iter()
+                        .map(|l| l.chars().count())
+                        .max()
+                        .unwrap_or(0)
+                        .max(title.chars().count() + 2);
+                    let (width, height) = (inner_width + 4, lines.len() + 2);
+                    for r in *row..*row + height {
+                        for c in *col..*col + width {
+                            screen.set_overlay(c, r, ' ', *color);
+                        }
+                    }
+                    draw_box(screen, *col, *row, width, height, *color);
+                    if !title.is_empty() {
+                        draw_text(screen, col + 2, *row, &format!(" {} ", title), *color);
+                    }
+                    for (i, line) in lines.iter().enumerate() {
+                        draw_text(screen, col + 2, row + 1 + i, line, *color);
+                    }
+                },
+            }
+        }
+    }
+}
+
+fn draw_text(screen: &mut Screen, col: usize, row: usize, text: &str, color: CellColor) {
+    for (i, character) in text.chars().enumerate() {
+        screen.set_overlay(col + i, row, character, color);
+    }
+}
+
+fn draw_box(screen: &mut Screen, col: usize, row: usize, width: usize, height: usize, color: CellColor) {
+    if width < 2 || height < 2 {
+        return;
+    }
+    let (right, bottom) = (col + width - 1, row + height - 1);
+    for c in col + 1..right {
+        screen.set_overlay(c, row, '-', color);
+        screen.set_overlay(c, bottom, '-', color);
+    }
+    for r in row + 1..bottom {
+        screen.set_overlay(col, r, '|', color);
+        screen.set_overlay(right, r, '|', color);
+    }
+    for (c, r) in [(col, row), (right, row), (col, bottom), (right, bottom)] {
+        screen.set_overlay(c, r, '+', color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::RenderMode;
+
+    fn rows(screen: &Screen) -> Vec<String> {
+        screen.to_snapshot().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn text_and_box_from_the_top_left() {
+        let mut screen = Screen::new(8, 4, RenderMode::Ascii);
+        let mut overlay = Overlay::new();
+        overlay.add_box(0, 1, 6, 3, CellColor::default());
+        overlay.add_text(1, 2, "hi", CellColor::default());
+        overlay.add_text(6, 0, "cut", CellColor::default());
+        overlay.draw(&mut screen);
+        assert_eq!(rows(&screen), vec!["      cu", "+----+", "|hi  |", "+----+"]);
+    }
+
+    // the box is only an outline, the frame shows through inside
+    #[test]
+    fn box_leaves_the_inside() {
+        let mut screen = Screen::new(3, 3, RenderMode::Ascii);
+        screen.set(1, 1, b'@', CellColor::default());
+        let mut overlay = Overlay::new();
+        overlay.add_box(0, 0, 3, 3, CellColor::default());
+        overlay.draw(&mut screen);
+        assert_eq!(rows(&screen), vec!["+-+", "|@|", "+-+"]);
+        // and the depth buffer is left alone
+        assert!(screen.z_buf.iter().flatten().all(|z| z.is_none()));
+    }
+
+    #[test]
+    fn panel_fits_its_lines_and_hides_the_frame() {
+        let mut screen = Screen::new(10, 4, RenderMode::Ascii);
+        screen.set(2, 1, b'@', CellColor::default());
+        let mut overlay = Overlay::new();
+        overlay.add_panel(0, 0, "t", vec![String::from("ab"), String::from("abcd")], CellColor::default());
+        overlay.draw(&mut screen);
+        assert_eq!(rows(&screen), vec!["+- t --+", "| ab   |", "| abcd |", "+------+"]);
+    }
+
+    #[test]
+    fn clear_removes_the_items() {
+        let mut overlay = Overlay::new();
+        overlay.add_text(0, 0, "x", CellColor::default());
+        overlay.clear();
+        let mut screen = Screen::new(2, 1, RenderMode::Ascii);
+        overlay.draw(&mut screen);
+        assert_eq!(rows(&screen), vec![""]);
+    }
+}
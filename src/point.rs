@@ -1,6 +1,7 @@
-use crate::constants::{HEIGHT, WIDTH};
 use std::f32::consts::PI;
 use crate::projection::Camera;
+use crate::display::Screen;
+use crate::math::Mat4;
 
 #[derive(Copy, Clone)]
 pub struct Point2d {
@@ -22,113 +23,71 @@ pub struct Point3d {
     pub z: f32,
 }
 
+pub const ORIGIN: Point3d = Point3d { x: 0.0, y: 0.0, z: 0.0 };
 
-impl Point3d {
-    pub fn project(&self, camera: &Camera) -> Option<Point2dZ> {
-        let relative = Point3d {
-            x: self.x - camera.pos.x,
-            y: self.y - camera.pos.y,
-            z: self.z - camera.pos.z,
-        };
 
-        if relative.z <= 110.0 {
+impl Point3d {
+    // Through a view-projection matrix, see Camera::view_projection. None
+    // for points whose depth isn't between `near` and `far`.
+    pub fn project(&self, view_projection: &Mat4, near: f32, far: f32) -> Option<Point2dZ> {
+        let [x, y, z, w] = view_projection.transform_homogeneous(self);
+        if z < near || z > far {
             return None;
         }
-
-        let scale = 100.0 / (relative.z as f32 - 100.0);
-
         return Some(Point2dZ {
-            x: (scale * relative.x) as i32 + (WIDTH as i32) / 2,
-            y: (scale * relative.y) as i32 + (HEIGHT as i32) / 2,
-            z: relative.z as i32
+            x: (x / w) as i32,
+            y: (y / w) as i32,
+            z: z as i32,
         });
     }
-    pub fn rotate_x(&self, center: &Point3d, rad: f32) -> Point3d {
-        let y: f32 = (self.y - center.y) as f32;
-        let z: f32 = (self.z - center.z) as f32;
-
-        let mut y_rot: f32 = -z * rad.sin() + y * rad.cos();
-        let mut z_rot: f32 = z * rad.cos() + y * rad.sin();
-
-        y_rot += center.y;
-        z_rot += center.z;
-
-        return Point3d {
-            x: self.x,
-            y: y_rot,
-            z: z_rot,
-        };
-    }
-    pub fn rotate_y(&self, center: &Point3d, rad: f32) -> Point3d {
-        let x: f32 = (self.x - center.x) as f32;
-        let z: f32 = (self.z - center.z) as f32;
-
-        let mut x_rot: f32 = x * rad.cos() + z * rad.sin();
-        let mut z_rot: f32 = -x * rad.sin() + z * rad.cos();
-
-        x_rot += center.x;
-        z_rot += center.z;
-
-        return Point3d {
-            x: x_rot,
-            y: self.y,
-            z: z_rot,
-        };
-    }
-    pub fn rotate_z(&self, center: &Point3d, rad: f32) -> Point3d {
-        let x: f32 = (self.x - center.x) as f32;
-        let y: f32 = (self.y - center.y) as f32;
-
-        let mut x_rot: f32 = x * rad.cos() + y * rad.sin();
-        let mut y_rot: f32 = -x * rad.sin() + y * rad.cos();
-
-        x_rot += center.x;
-        y_rot += center.y;
-
-        return Point3d {
-            x: x_rot,
-            y: y_rot,
-            z: self.z,
-        };
+    // from self at 0.0 to other at 1.0
+    pub fn lerp(&self, other: &Point3d, t: f32) -> Point3d {
+        Point3d {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            z: self.z + (other.z - self.z) * t,
+        }
     }
-    pub fn rotate_y_degrees(&self, center: &Point3d, degrees: f32) -> Point3d {
-        return self.rotate_y(center, degrees.to_radians());
+    pub fn minus(&self, other: &Point3d) -> Point3d {
+        Point3d { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z }
     }
-    pub fn rotate_y_mut(&mut self, center: &Point3d, rad: f32) {
-        let x: f32 = (self.x - center.x) as f32;
-        let z: f32 = (self.z - center.z) as f32;
-
-        self.x = x * rad.cos() + z * rad.sin() + center.x;
-        self.z = -x * rad.sin() + z * rad.cos() + center.z;
+    pub fn dot(&self, other: &Point3d) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
     }
-    pub fn rotate_x_mut(&mut self, center: &Point3d, rad: f32) {
-        let y: f32 = (self.y - center.y) as f32;
-        let z: f32 = (self.z - center.z) as f32;
-
-        self.y = -z * rad.sin() + y * rad.cos() + center.y;
-        self.z = z * rad.cos() + y * rad.sin() + center.z;
+    pub fn cross(&self, other: &Point3d) -> Point3d {
+        Point3d {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
     }
-    pub fn rotate_z_mut(&mut self, center: &Point3d, rad: f32) {
-        let x: f32 = (self.x - center.x) as f32;
-        let y: f32 = (self.y - center.y) as f32;
-
-        self.x = x * rad.cos() + y * rad.sin() + center.x;
-        self.y = -x * rad.sin() + y * rad.cos() + center.y;
+    // same direction with a length of 1, or all zeros for a zero vector
+    pub fn normalized(&self) -> Point3d {
+        let length = self.dot(self).sqrt();
+        if length == 0.0 {
+            return *self;
+        }
+        Point3d { x: self.x / length, y: self.y / length, z: self.z / length }
     }
 }
 impl Camera {
+    // pitch, yaw and roll as x, y and z, see Camera::euler
     pub fn rotation_degrees(&self) -> Point3d {
+        let rot = self.euler();
         return Point3d {
-            x: self.rot.x * 180.0 / PI,
-            y: self.rot.y * 180.0 / PI,
-            z: self.rot.z * 180.0 / PI,
+            x: rot.x * 180.0 / PI,
+            y: rot.y * 180.0 / PI,
+            z: rot.z * 180.0 / PI,
         };
     }
+    pub fn from_rotation_degrees(pos: Point3d, degrees: &Point3d) -> Camera {
+        Camera::from_euler(pos, degrees.y * PI / 180.0, degrees.x * PI / 180.0, degrees.z * PI / 180.0)
+    }
 }
 
 impl Point2d {
-    pub fn is_in_screen(&self) -> bool {
-        return self.x >= 0 && self.x < WIDTH as i32 && self.y >= 0 && self.y < HEIGHT as i32;
+    pub fn is_in_screen(&self, screen: &Screen) -> bool {
+        screen.contains(self.x, self.y)
     }
 }
 
@@ -166,8 +125,8 @@ impl std::fmt::Display for Camera {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "POS: ({}, {}, {}) ROT: ({}, {}, {}) ",
-            self.pos.x, self.pos.y, self.pos.z, self.rot.x, self.rot.y, self.rot.z
+            "POS: ({}, {}, {}) ROT: {} ",
+            self.pos.x, self.pos.y, self.pos.z, self.rotation_degrees()
         )
     }
 }
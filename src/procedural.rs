@@ -3,11 +3,19 @@ use rand::seq::SliceRandom;
 use crate::projection::Shape3d;
 use crate::triangle::Triangle3d;
 use crate::point::Point3d;
+use crate::color::{CellColor, Rgb};
+use crate::light::{self, Shading};
+use crate::material::Material;
+use std::rc::Rc;
 
 const WIDTH: i64 = 30;
 const HEIGHT: i64 = 30;
 const WIDTH_U: usize = WIDTH as usize;
 const HEIGHT_U: usize = HEIGHT as usize;
+const LOW_COLOR: Rgb = Rgb { r: 40, g: 110, b: 40 };
+const HIGH_COLOR: Rgb = Rgb { r: 190, g: 170, b: 130 };
+// How many colors the terrain goes through from low to high ground
+const HEIGHT_BANDS: usize = 8;
 
 pub fn generate_shape_height_map(fill: u8) -> Vec<Shape3d> {
     let mut map_opt = [[None; WIDTH_U]; HEIGHT_U];
@@ -40,16 +48,30 @@ pub fn generate_map(y: i64, x: i64, map: &mut [[Option<i32>; WIDTH_U]; HEIGHT_U]
 
 pub fn shapes_from_map(fill: u8, map: &mut [[i32; WIDTH_U]; HEIGHT_U]) -> Vec<Shape3d> {
     let mut shapes: Vec<Shape3d> = vec![];
+    let terrain = Material {
+        name: String::from("terrain"),
+        fill,
+        border: Some(b' '),
+        shading: Shading::Smooth,
+        ..Material::default()
+    };
+    // every triangle gets the color of its own height, the triangles of a
+    // band share its material
+    let materials: Vec<Rc<Material>> = (0..HEIGHT_BANDS)
+        .map(|band| Rc::new(Material { color: band_color(band), ..terrain.clone() }))
+        .collect();
+    let material_at = |heights: &[i32; 3]| materials[height_band(heights)].clone();
     for r in 0..(map.len() - 1) {
         for c in 0..(map[0].len() - 1) {
             let t = Triangle3d {
                 points: [
                     Point3d {x: (c*150) as f32, y: map[r][c] as f32, z: (r*150) as f32},
-                    Point3d {x: ((c+1)*150) as f32, y: map[r][c+1] as f32, z: (r*150) as f32},
                     Point3d {x: (c*150) as f32, y: map[r+1][c] as f32, z: ((r+1)*150) as f32},
+                    Point3d {x: ((c+1)*150) as f32, y: map[r][c+1] as f32, z: (r*150) as f32},
                 ],
-                fill,
-                border: Some(b' '),
+                normals: None,
+                uvs: None,
+                material: material_at(&[map[r][c], map[r+1][c], map[r][c+1]]),
             };
             let t2 = Triangle3d {
                 points: [
@@ -57,16 +79,32 @@ pub fn shapes_from_map(fill: u8, map: &mut [[i32; WIDTH_U]; HEIGHT_U]) -> Vec<Sh
                     Point3d {x: ((c+1)*150) as f32, y: map[r][c+1] as f32, z: (r*150) as f32},
                     Point3d {x: (c*150) as f32, y: map[r+1][c] as f32, z: ((r+1)*150) as f32},
                 ],
-                fill,
-                border: Some(b' '),
+                normals: None,
+                uvs: None,
+                material: material_at(&[map[r+1][c+1], map[r][c+1], map[r+1][c]]),
             };
             shapes.push(Shape3d::Triangle(t));
             shapes.push(Shape3d::Triangle(t2));
         }
     }
+    light::compute_vertex_normals(&mut shapes);
     return shapes;
 }
 
+// Low ground is green, high ground fades to a dry brown
+fn band_color(band: usize) -> CellColor {
+    CellColor {
+        fg: Some(LOW_COLOR.lerp(&HIGH_COLOR, band as f32 / (HEIGHT_BANDS - 1) as f32)),
+        bg: None,
+    }
+}
+
+fn height_band(heights: &[i32; 3]) -> usize {
+    let average = (heights[0] + heights[1] + heights[2]) as f32 / 3.0;
+    let t = ((average + 200.0) / 400.0).clamp(0.0, 1.0);
+    (t * (HEIGHT_BANDS - 1) as f32).round() as usize
+}
+
 pub fn print_map(map: &mut [[Option<i32>; WIDTH_U]; HEIGHT_U]) {
     for row in map {
         for cell in row {
@@ -81,3 +119,28 @@ pub fn print_map(map: &mut [[Option<i32>; WIDTH_U]; HEIGHT_U]) {
 }
 
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terrain_shares_a_material_per_band() {
+        let mut map = [[0; WIDTH_U]; HEIGHT_U];
+        for (r, row) in map.iter_mut().enumerate() {
+            for (c, height) in row.iter_mut().enumerate() {
+                *height = (r as i32 - c as i32) * 15;
+            }
+        }
+        let shapes = shapes_from_map(b'-', &mut map);
+        let mut materials: Vec<Rc<Material>> = vec![];
+        for s in &shapes {
+            if let Shape3d::Triangle(t) = s
+                && !materials.iter().any(|m| Rc::ptr_eq(m, &t.material))
+            {
+                materials.push(t.material.clone());
+            }
+        }
+        assert_eq!(materials.len(), HEIGHT_BANDS);
+    }
+}
@@ -1,12 +1,42 @@
 use crate::triangle;
 use crate::line;
 use crate::point;
+use crate::display::Screen;
+use crate::math::{Mat3, Mat4, Transform};
+use num_quaternion::UQ32;
+use std::f32::consts::PI;
+
+// Vertical field of view of a new camera, and how far it can zoom
+pub const DEFAULT_FOV: f32 = 45.0 * PI / 180.0;
+pub const MIN_FOV: f32 = 10.0 * PI / 180.0;
+pub const MAX_FOV: f32 = 120.0 * PI / 180.0;
+// Height of a terminal cell over its width
+pub const CELL_ASPECT: f32 = 2.0;
+// World units across the screen's height of a new orthographic camera
+pub const DEFAULT_ORTHO_HEIGHT: f32 = 1000.0;
+// How far the orthographic projection can zoom, in world units
+pub const MIN_ORTHO_HEIGHT: f32 = 50.0;
+pub const MAX_ORTHO_HEIGHT: f32 = 20000.0;
+// How far the top, front and side views are put from what they look at
+pub const ORTHO_DISTANCE: f32 = 10000.0;
+// Points nearer than this or farther than that aren't drawn
+pub const DEFAULT_NEAR: f32 = 10.0;
+pub const DEFAULT_FAR: f32 = 20000.0;
+// How far short of the near plane a corner cut onto it by clip_near can
+// project, the matrix works its depth out with other rounding than View::depth
+pub const CLIP_TOLERANCE: f32 = 0.01;
 
 pub enum Shape3d {
     Triangle(triangle::Triangle3d),
     Line(line::Line3d)       
 }
 impl Shape3d {
+    pub fn transformed(&self, transform: &Transform) -> Shape3d {
+        match self {
+            Shape3d::Triangle(t) => Shape3d::Triangle(t.transformed(transform)),
+            Shape3d::Line(l) => Shape3d::Line(l.transformed(transform)),
+        }
+    }
     pub fn translate(&mut self, x: f32, y: f32, z: f32) {
         match self {
             Shape3d::Triangle(t) => {
@@ -22,23 +52,309 @@ impl Shape3d {
     }
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum Projection {
+    // farther things are smaller, with the camera's fov
+    Perspective,
+    // parallel lines stay parallel, the camera's ortho_height fits the screen
+    Orthographic,
+}
+
+#[derive(Copy, Clone)]
 pub struct Camera {
     pub pos: point::Point3d,
-    pub rot: point::Point3d,
+    // turns the camera's own axes, x to its right, y up and z forward, into
+    // the world's
+    pub orientation: UQ32,
+    pub projection: Projection,
+    // vertical, in radians
+    pub fov: f32,
+    // the zoom of the orthographic projection, in world units
+    pub ortho_height: f32,
+    // the shape of the screen's cells, see CELL_ASPECT, 1.0 for square ones
+    pub cell_aspect: f32,
+    // view depth limits, see Point3d::project
+    pub near: f32,
+    pub far: f32,
 }
 
-pub fn sort_by_farthest(vector: &mut Vec<Shape3d>, camera: &Camera) {
-    let distance = |s: &Shape3d, p: &point::Point3d| match s {
-        Shape3d::Triangle(t) => point::distance(&t.center(), p),
-        Shape3d::Line(l) => point::distance(&l.center(), p),
-    };
-    for i in 1..vector.len() {
-        let mut p_idx = i;
-        let tmp_distance = distance(&vector[i], &camera.pos);
-        while p_idx != 0 && tmp_distance > distance(&vector[p_idx - 1], &camera.pos) {
-            vector.swap(p_idx, p_idx - 1);
-            p_idx -= 1;
+// a turn of `rads` around an axis of length 1
+fn axis_turn(axis: [f32; 3], rads: f32) -> UQ32 {
+    UQ32::from_rotation_vector(&axis.map(|a| a * rads))
+}
+
+impl Camera {
+    // At `pos` looking along +z, with y up
+    pub fn new(pos: point::Point3d) -> Camera {
+        Camera {
+            pos,
+            orientation: UQ32::ONE,
+            projection: Projection::Perspective,
+            fov: DEFAULT_FOV,
+            ortho_height: DEFAULT_ORTHO_HEIGHT,
+            cell_aspect: CELL_ASPECT,
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+        }
+    }
+
+    // Yaw turns toward +x around the world's y, pitch up and roll tips the
+    // camera's right side up, applied roll first, then pitch, then yaw
+    pub fn from_euler(pos: point::Point3d, yaw: f32, pitch: f32, roll: f32) -> Camera {
+        let orientation = axis_turn([0.0, 1.0, 0.0], yaw) * axis_turn([1.0, 0.0, 0.0], -pitch) * axis_turn([0.0, 0.0, 1.0], roll);
+        Camera { orientation, ..Camera::new(pos) }
+    }
+
+    // The angles of from_euler, as x: pitch, y: yaw and z: roll. Looking
+    // straight up or down the roll can't be told from the yaw and is 0.
+    pub fn euler(&self) -> point::Point3d {
+        let (forward, right, up) = (self.forward(), self.right(), self.up());
+        let pitch = forward.y.clamp(-1.0, 1.0).asin();
+        if pitch.cos() < 1e-4 {
+            return point::Point3d { x: pitch, y: (-right.z).atan2(right.x), z: 0.0 };
+        }
+        point::Point3d { x: pitch, y: forward.x.atan2(forward.z), z: right.y.atan2(up.y) }
+    }
+
+    // A camera at `pos` whose view goes along `direction`, with no roll
+    pub fn looking_along(pos: point::Point3d, direction: &point::Point3d) -> Camera {
+        let horizontal = (direction.x * direction.x + direction.z * direction.z).sqrt();
+        Camera::from_euler(pos, direction.x.atan2(direction.z), direction.y.atan2(horizontal), 0.0)
+    }
+
+    // Around the world's y, so the horizon stays where it is
+    pub fn yaw(&mut self, rads: f32) {
+        self.orientation = (axis_turn([0.0, 1.0, 0.0], rads) * self.orientation).adjust_norm();
+    }
+    // Around the camera's own x, positive looks up
+    pub fn pitch(&mut self, rads: f32) {
+        self.orientation = (self.orientation * axis_turn([1.0, 0.0, 0.0], -rads)).adjust_norm();
+    }
+    // Around the camera's own z, positive tips its right side up
+    pub fn roll(&mut self, rads: f32) {
+        self.orientation = (self.orientation * axis_turn([0.0, 0.0, 1.0], rads)).adjust_norm();
+    }
+
+    // The camera's own axes in the world, of length 1
+    pub fn forward(&self) -> point::Point3d {
+        let [x, y, z] = self.orientation.rotate_vector([0.0, 0.0, 1.0]);
+        point::Point3d { x, y, z }
+    }
+    pub fn right(&self) -> point::Point3d {
+        let [x, y, z] = self.orientation.rotate_vector([1.0, 0.0, 0.0]);
+        point::Point3d { x, y, z }
+    }
+    pub fn up(&self) -> point::Point3d {
+        let [x, y, z] = self.orientation.rotate_vector([0.0, 1.0, 0.0]);
+        point::Point3d { x, y, z }
+    }
+
+    // Part of the way to `other`, 0.0 is self and 1.0 is other. The
+    // orientation turns at an even speed along the shortest way.
+    pub fn slerp(&self, other: &Camera, t: f32) -> Camera {
+        Camera {
+            pos: self.pos.lerp(&other.pos, t),
+            orientation: self.orientation.slerp(&other.orientation, t),
+            fov: self.fov + (other.fov - self.fov) * t,
+            ortho_height: self.ortho_height + (other.ortho_height - self.ortho_height) * t,
+            ..*self
+        }
+    }
+
+    // View to world space, the camera's own axes turned and moved to where
+    // it is, like the model matrix of a shape
+    pub fn world_matrix(&self) -> Mat4 {
+        Mat4::translation(&self.pos) * Mat4::from_mat3(&Mat3::from_rotation(&self.orientation))
+    }
+
+    // World to view space, the camera at the origin looking along +z
+    pub fn view_matrix(&self) -> Mat4 {
+        self.world_matrix().inverse().expect("A camera's turn and move can always be undone")
+    }
+
+    // Narrows or widens the view by `factor`, the fov within MIN_FOV and
+    // MAX_FOV, the ortho_height within MIN_ORTHO_HEIGHT and MAX_ORTHO_HEIGHT
+    pub fn zoom(&mut self, factor: f32) {
+        match self.projection {
+            Projection::Perspective => self.fov = (self.fov / factor).clamp(MIN_FOV, MAX_FOV),
+            Projection::Orthographic => self.ortho_height = (self.ortho_height / factor).clamp(MIN_ORTHO_HEIGHT, MAX_ORTHO_HEIGHT),
+        }
+    }
+
+    // Orthographic cameras centered on `center` that fit `height` world
+    // units in the screen's height. The top view has +z up on the screen,
+    // the front view looks along +z and the side view along -x, with y up.
+    pub fn top_view(center: &point::Point3d, height: f32) -> Camera {
+        Camera::orthographic_view(center, &point::Point3d { x: 0.0, y: -1.0, z: 0.0 }, height)
+    }
+    pub fn front_view(center: &point::Point3d, height: f32) -> Camera {
+        Camera::orthographic_view(center, &point::Point3d { x: 0.0, y: 0.0, z: 1.0 }, height)
+    }
+    pub fn side_view(center: &point::Point3d, height: f32) -> Camera {
+        Camera::orthographic_view(center, &point::Point3d { x: -1.0, y: 0.0, z: 0.0 }, height)
+    }
+    fn orthographic_view(center: &point::Point3d, direction: &point::Point3d, height: f32) -> Camera {
+        let pos = point::Point3d {
+            x: center.x - direction.x * ORTHO_DISTANCE,
+            y: center.y - direction.y * ORTHO_DISTANCE,
+            z: center.z - direction.z * ORTHO_DISTANCE,
+        };
+        Camera {
+            projection: Projection::Orthographic,
+            ortho_height: height,
+            ..Camera::looking_along(pos, direction)
         }
     }
+
+    // Width over height of the screen as it's seen, its pixels are only
+    // square when cell_aspect matches the mode's pixels per cell
+    pub fn aspect(&self, screen: &Screen) -> f32 {
+        let (cell_width, cell_height) = screen.mode.cell_size();
+        let pixel_aspect = self.cell_aspect * cell_width as f32 / cell_height as f32;
+        screen.width as f32 / (screen.height as f32 * pixel_aspect)
+    }
+
+    // View space to the pixels of `screen`, centered on it
+    pub fn projection_matrix(&self, screen: &Screen) -> Mat4 {
+        let (aspect, width, height) = (self.aspect(screen), screen.width as f32, screen.height as f32);
+        match self.projection {
+            Projection::Perspective => Mat4::perspective(self.fov, aspect, width, height),
+            Projection::Orthographic => Mat4::orthographic(self.ortho_height, aspect, width, height),
+        }
+    }
+
+    // Everything from world space to the screen in one matrix
+    pub fn view_projection(&self, screen: &Screen) -> Mat4 {
+        self.projection_matrix(screen) * self.view_matrix()
+    }
+
+    pub fn view(&self, screen: &Screen) -> View {
+        View {
+            pos: self.pos,
+            forward: self.forward(),
+            view_projection: self.view_projection(screen),
+            near: self.near,
+            far: self.far,
+            perspective: self.projection == Projection::Perspective,
+        }
+    }
+}
+
+// What shapes need from a camera to be drawn into one screen, worked out
+// once per frame instead of for every shape. The shapes stay as they are in
+// world space, so any number of cameras can render the same scene.
+#[derive(Copy, Clone)]
+pub struct View {
+    pub pos: point::Point3d,
+    pub forward: point::Point3d,
+    pub view_projection: Mat4,
+    pub near: f32,
+    pub far: f32,
+    pub perspective: bool,
+}
+
+impl View {
+    // How far in front of the camera `p` is, along its view
+    pub fn depth(&self, p: &point::Point3d) -> f32 {
+        p.minus(&self.pos).dot(&self.forward)
+    }
+
+    pub fn project(&self, p: &point::Point3d) -> Option<point::Point2dZ> {
+        p.project(&self.view_projection, self.near, self.far)
+    }
+
+    // For the corners of shapes that went through clip_near
+    pub fn project_clipped(&self, p: &point::Point3d) -> Option<point::Point2dZ> {
+        p.project(&self.view_projection, self.near - CLIP_TOLERANCE, self.far)
+    }
 }
 
+// The smallest and largest x, y and z of the shapes' points, None when
+// there are no shapes
+pub fn bounds(shapes: &[Shape3d]) -> Option<(point::Point3d, point::Point3d)> {
+    let mut points = shapes.iter().flat_map(|s| match s {
+        Shape3d::Triangle(t) => t.points.to_vec(),
+        Shape3d::Line(l) => vec![l.p1, l.p2],
+    });
+    let first = points.next()?;
+    Some(points.fold((first, first), |(min, max), p| (
+        point::Point3d { x: min.x.min(p.x), y: min.y.min(p.y), z: min.z.min(p.z) },
+        point::Point3d { x: max.x.max(p.x), y: max.y.max(p.y), z: max.z.max(p.z) },
+    )))
+}
+
+pub fn sort_by_farthest(vector: &mut [&Shape3d], camera: &Camera) {
+    let distance = |s: &Shape3d| match s {
+        Shape3d::Triangle(t) => point::distance(&t.center(), &camera.pos),
+        Shape3d::Line(l) => point::distance(&l.center(), &camera.pos),
+    };
+    vector.sort_by(|a, b| distance(b).partial_cmp(&distance(a)).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::Point3d;
+    use crate::testing::close;
+
+    #[test]
+    fn rotation_degrees_round_trip() {
+        let pos = Point3d { x: 1.0, y: 2.0, z: 3.0 };
+        for (pitch, yaw, roll) in [(0.0, 0.0, 0.0), (30.0, 0.0, 0.0), (0.0, 120.0, 0.0), (0.0, 0.0, -45.0), (-60.0, -150.0, 170.0), (85.0, 45.0, 10.0)] {
+            let degrees = Point3d { x: pitch, y: yaw, z: roll };
+            let camera = Camera::from_rotation_degrees(pos, &degrees);
+            assert!(close(&camera.rotation_degrees(), &degrees), "{} came back as {}", degrees, camera.rotation_degrees());
+        }
+    }
+
+    // yaw turns toward +x, pitch looks up and roll tips the right side up
+    #[test]
+    fn from_rotation_degrees_turns_the_axes() {
+        let camera = Camera::from_rotation_degrees(point::ORIGIN, &Point3d { x: 0.0, y: 90.0, z: 0.0 });
+        assert!(close(&camera.forward(), &Point3d { x: 1.0, y: 0.0, z: 0.0 }));
+        let camera = Camera::from_rotation_degrees(point::ORIGIN, &Point3d { x: 90.0, y: 0.0, z: 0.0 });
+        assert!(close(&camera.forward(), &Point3d { x: 0.0, y: 1.0, z: 0.0 }));
+        let camera = Camera::from_rotation_degrees(point::ORIGIN, &Point3d { x: 0.0, y: 0.0, z: 90.0 });
+        assert!(close(&camera.right(), &Point3d { x: 0.0, y: 1.0, z: 0.0 }));
+    }
+
+    #[test]
+    fn sort_by_farthest_puts_the_far_shapes_first() {
+        let line = |z: f32| Shape3d::Line(line::Line3d {
+            p1: Point3d { x: 0.0, y: 0.0, z },
+            p2: Point3d { x: 10.0, y: 0.0, z },
+            material: std::rc::Rc::new(crate::material::Material::default()),
+        });
+        let shapes = [line(100.0), line(-300.0), line(50.0), line(200.0)];
+        let mut sorted: Vec<&Shape3d> = shapes.iter().collect();
+        sort_by_farthest(&mut sorted, &Camera::new(point::ORIGIN));
+        let depths: Vec<f32> = sorted.iter().map(|s| match s {
+            Shape3d::Line(l) => l.p1.z,
+            Shape3d::Triangle(t) => t.points[0].z,
+        }).collect();
+        assert_eq!(depths, vec![-300.0, 200.0, 100.0, 50.0]);
+    }
+
+    #[test]
+    fn slerp_ends_and_middle() {
+        let from = Camera { fov: MIN_FOV, ..Camera::from_euler(Point3d { x: 0.0, y: 0.0, z: 0.0 }, 0.0, 0.0, 0.0) };
+        let to = Camera { fov: MAX_FOV, ..Camera::from_euler(Point3d { x: 100.0, y: -20.0, z: 40.0 }, PI / 2.0, 0.0, 0.0) };
+
+        let start = from.slerp(&to, 0.0);
+        assert!(close(&start.pos, &from.pos) && close(&start.forward(), &from.forward()));
+        assert!((start.fov - from.fov).abs() < 1e-6);
+
+        let end = from.slerp(&to, 1.0);
+        assert!(close(&end.pos, &to.pos) && close(&end.forward(), &to.forward()));
+        assert!((end.fov - to.fov).abs() < 1e-6);
+
+        // half way along the turn, at an even speed
+        let middle = from.slerp(&to, 0.5);
+        assert!(close(&middle.pos, &Point3d { x: 50.0, y: -10.0, z: 20.0 }));
+        let diagonal = (0.5f32).sqrt();
+        assert!(close(&middle.forward(), &Point3d { x: diagonal, y: 0.0, z: diagonal }));
+        assert!(close(&middle.up(), &Point3d { x: 0.0, y: 1.0, z: 0.0 }));
+        assert!((middle.fov - (MIN_FOV + MAX_FOV) / 2.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::display::{RenderMode, Screen};
+use crate::fog::Fog;
+use crate::light::{self, Light, Shading};
+use crate::math::{Mat4, Transform};
+use crate::projection::{self, Camera, Shape3d, View};
+use crate::shadow::ShadowMap;
+use crate::triangle::Triangle3d;
+
+// the default draws every shape unlit, without shadows or fog
+#[derive(Default)]
+pub struct RenderSettings {
+    // with no lights every triangle is drawn with its own fill
+    pub lights: Vec<Light>,
+    // darken what the main light can't see, see shadow.rs
+    pub shadows: bool,
+    // fades distant pixels, applied after the shadows
+    pub fog: Option<Fog>,
+}
+
+// Draws the shapes as seen from the camera into the screen's buffers, on top
+// of whatever is already there. `model` places the shapes in the world, the
+// camera's view and projection follow it, so the whole way to the screen is
+// projection * view * model. Lights and shadows are worked out in the world,
+// the shapes are moved there once per frame unless the model is the identity.
+pub fn render_shapes(shapes: &[Shape3d], model: &Mat4, camera: &Camera, settings: &RenderSettings, screen: &mut Screen) {
+    let in_world: Vec<Shape3d>;
+    let shapes = if *model == Mat4::IDENTITY {
+        shapes
+    } else {
+        let transform = Transform::new(model);
+        in_world = shapes.iter().map(|s| s.transformed(&transform)).collect();
+        &in_world
+    };
+    let view = camera.view(screen);
+    let mut transparent: Vec<&Shape3d> = vec![];
+    for s in shapes {
+        match s {
+            Shape3d::Triangle(t) if t.material.transparency > 0.0 => transparent.push(s),
+            // the borders of a transparent triangle go with it
+            Shape3d::Line(l) if l.material.transparency > 0.0 => transparent.push(s),
+            _ => render_shape(s, &view, settings, screen),
+        }
+    }
+    // they don't write depth, so they go over everything opaque and the far
+    // ones have to be drawn before the near ones
+    projection::sort_by_farthest(&mut transparent, camera);
+    for s in transparent {
+        render_shape(s, &view, settings, screen);
+    }
+    if settings.shadows
+        && let Some(shadow_map) = ShadowMap::build(shapes, &settings.lights, &camera.pos) {
+        shadow_map.apply(screen);
+    }
+    if let Some(fog) = &settings.fog {
+        fog.apply(screen);
+    }
+}
+
+fn render_shape(s: &Shape3d, view: &View, settings: &RenderSettings, screen: &mut Screen) {
+    match s {
+        Shape3d::Triangle(t) => {
+            for piece in t.clip_near(view) {
+                render_triangle(&piece, view, settings, screen);
+            }
+        },
+        Shape3d::Line(l) => {
+            if let Some(l) = l.clip_near(view) {
+                l.project(view).add_to_grid(screen);
+            }
+        },
+    }
+}
+
+fn render_triangle(t: &Triangle3d, view: &View, settings: &RenderSettings, screen: &mut Screen) {
+    let mut t_2d = t.project(view);
+    if t_2d.is_culled() {
+        return;
+    }
+    if !settings.lights.is_empty() {
+        match t.material.shading {
+            Shading::None => (),
+            Shading::Flat => {
+                let intensity = light::intensity_at(&settings.lights, &t.center(), &t.normal());
+                t_2d.shade = Some([intensity; 3]);
+            },
+            Shading::Smooth => {
+                let normals = t.normals.unwrap_or([t.normal(); 3]);
+                t_2d.shade = Some([0, 1, 2].map(|i| light::intensity_at(&settings.lights, &t.points[i], &normals[i])));
+            },
+        }
+    }
+    if screen.mode == RenderMode::Braille {
+        t_2d.add_wireframe_to_grid(screen);
+    } else {
+        t_2d.add_to_grid(screen);
+        t_2d.add_border_to_grid(screen);
+    }
+}
+
+// Renders a single frame without a terminal, for headless runs and snapshots
+pub fn render_offscreen(shapes: &[Shape3d], model: &Mat4, camera: &Camera, settings: &RenderSettings, cols: usize, rows: usize, mode: RenderMode) -> Screen {
+    let mut screen = Screen::new(cols, rows, mode);
+    render_shapes(shapes, model, camera, settings, &mut screen);
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::read_data_file;
+    use crate::math::Vec3;
+    use crate::point::Point3d;
+
+    // shapes moved by a model matrix look the same to a camera moved along
+    // with them
+    #[test]
+    fn model_moves_the_shapes() {
+        let shapes = read_data_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/pyramid.dat")).unwrap();
+        let settings = RenderSettings::default();
+        let offset = Vec3 { x: 500.0, y: -200.0, z: 1000.0 };
+        let camera = Camera::new(Point3d { x: 30.0, y: 20.0, z: 0.0 });
+        let moved_camera = Camera::new(Point3d { x: 30.0 + offset.x, y: 20.0 + offset.y, z: offset.z });
+
+        let expected = render_offscreen(&shapes, &Mat4::IDENTITY, &camera, &settings, 40, 20, RenderMode::Ascii);
+        let actual = render_offscreen(&shapes, &Mat4::translation(&offset), &moved_camera, &settings, 40, 20, RenderMode::Ascii);
+        assert!(expected.to_snapshot().contains('B'));
+        assert_eq!(actual.to_snapshot(), expected.to_snapshot());
+    }
+
+    // a mirrored model keeps its fronts facing out, so culling still works
+    #[test]
+    fn mirrored_model_keeps_its_fronts() {
+        let shapes = read_data_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/pyramid.dat")).unwrap();
+        let settings = RenderSettings::default();
+        let camera = Camera::new(Point3d { x: -30.0, y: 20.0, z: 0.0 });
+        let mirror = Mat4::scale(-1.0, 1.0, 1.0);
+        let screen = render_offscreen(&shapes, &mirror, &camera, &settings, 40, 20, RenderMode::Ascii);
+        // B faces the camera, A at the back faces away and is culled
+        let snapshot = screen.to_snapshot();
+        assert!(snapshot.contains('B'));
+        assert!(!snapshot.contains('A'));
+    }
+
+    // the glass is listed before the wall behind it, its border still shows
+    #[test]
+    fn transparent_borders_are_drawn_after_the_opaque_shapes() {
+        let shapes = read_data_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/glass_wall.dat")).unwrap();
+        let settings = RenderSettings::default();
+        let camera = Camera::new(Point3d { x: 0.0, y: 0.0, z: 0.0 });
+        let snapshot = render_offscreen(&shapes, &Mat4::IDENTITY, &camera, &settings, 40, 20, RenderMode::Ascii).to_snapshot();
+        assert!(snapshot.contains('#'));
+        assert!(snapshot.contains('*'));
+    }
+}
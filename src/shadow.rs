@@ -0,0 +1,101 @@
+use std::f32::consts::PI;
+
+use crate::display::{RenderMode, Screen};
+use crate::light::{self, Light};
+use crate::point::Point3d;
+use crate::projection::{Camera, Shape3d, View};
+
+// Size of the depth map, in pixels on each side
+const MAP_SIZE: usize = 320;
+// How far back a directional light is put from the point it looks at. The
+// depth map is a perspective view, so its shadows spread out a little as if
+// the light were a point this far away.
+const DIRECTIONAL_DISTANCE: f32 = 4000.0;
+// Vertical and horizontal field of view of the light
+const FOV: f32 = 116.0 * PI / 180.0;
+// A surface has to be this much farther from the light than the depth map
+// says before it counts as occluded, so faces don't shadow themselves
+const BIAS: f32 = 50.0;
+// Ramp steps and color factor taken off a cell in shadow
+const DARKEN_STEPS: usize = 3;
+const DARKEN_COLOR: f32 = 0.5;
+
+// Depth of the scene as seen from the light, rendered into a screen of its own
+pub struct ShadowMap {
+    pub depth: Screen,
+    // the light camera's, kept for every lookup
+    pub view: View,
+}
+
+impl ShadowMap {
+    // Renders the depth map of the main light, the first directional, point
+    // or spot light in the list. None when there are only ambient lights.
+    // `focus` is what a point light looks at and what a directional light is
+    // centered on, usually the viewer's position.
+    pub fn build(shapes: &[Shape3d], lights: &[Light], focus: &Point3d) -> Option<ShadowMap> {
+        let camera = lights.iter().find_map(|l| light_camera(l, focus))?;
+        let mut depth = Screen::new(MAP_SIZE, MAP_SIZE, RenderMode::Ascii);
+        let view = camera.view(&depth);
+        for s in shapes {
+            if let Shape3d::Triangle(t) = s {
+                for piece in t.clip_near(&view) {
+                    piece.project(&view).add_to_grid(&mut depth);
+                }
+            }
+        }
+        Some(ShadowMap { depth, view })
+    }
+
+    // true when something nearer to the light covers `p`, points outside the
+    // depth map are lit
+    pub fn is_occluded(&self, p: &Point3d) -> bool {
+        let projected = match self.view.project(p) {
+            Some(projected) => projected,
+            None => return false,
+        };
+        if !self.depth.contains(projected.x, projected.y) {
+            return false;
+        }
+        // the z buffer holds the depth plus the rasterizer's own offset of 5
+        match self.depth.z_buf[projected.y as usize][projected.x as usize] {
+            Some(nearest) => projected.z as f32 + 5.0 > nearest + BIAS,
+            None => false,
+        }
+    }
+
+    // Darkens the glyph and color of every pixel of the screen whose surface
+    // is hidden from the light
+    pub fn apply(&self, screen: &mut Screen) {
+        for y in 0..screen.height {
+            for x in 0..screen.width {
+                let p = match screen.world[y][x] {
+                    Some(p) => p,
+                    None => continue,
+                };
+                if self.is_occluded(&p) {
+                    screen.grid[y][x] = light::darken_glyph(screen.grid[y][x], DARKEN_STEPS);
+                    screen.color[y][x] = light::shade_color(&screen.color[y][x], DARKEN_COLOR);
+                }
+            }
+        }
+    }
+}
+
+fn light_camera(light: &Light, focus: &Point3d) -> Option<Camera> {
+    let camera = match light {
+        Light::Ambient { .. } => return None,
+        Light::Directional { direction, .. } => {
+            let back = direction.normalized();
+            let pos = Point3d {
+                x: focus.x - back.x * DIRECTIONAL_DISTANCE,
+                y: focus.y - back.y * DIRECTIONAL_DISTANCE,
+                z: focus.z - back.z * DIRECTIONAL_DISTANCE,
+            };
+            Camera::looking_along(pos, direction)
+        },
+        Light::Point { position, .. } => Camera::looking_along(*position, &focus.minus(position)),
+        Light::Spot { position, direction, .. } => Camera::looking_along(*position, direction),
+    };
+    // the depth map's pixels are square, whatever the terminal's are
+    Some(Camera { fov: FOV, cell_aspect: 1.0, ..camera })
+}
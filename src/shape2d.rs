@@ -0,0 +1,270 @@
+use crate::display::{Pixel, Screen, Shape2d};
+use crate::point::Point2d;
+use std::ops::RangeInclusive;
+
+// Screen space shapes for menus, crosshairs, gauges and the like. Coordinates
+// are pixels of the screen, with y = 0 at the bottom like the rest of the grid.
+
+pub struct Rect {
+    pub p1: Point2d,
+    pub p2: Point2d,
+    pub pixel: Pixel,
+}
+
+pub struct FilledRect {
+    pub p1: Point2d,
+    pub p2: Point2d,
+    pub pixel: Pixel,
+}
+
+pub struct Ellipse {
+    pub center: Point2d,
+    pub radius_x: i32,
+    pub radius_y: i32,
+    pub filled: bool,
+    pub pixel: Pixel,
+}
+
+// closed, the last point connects back to the first
+pub struct Polygon {
+    pub points: Vec<Point2d>,
+    pub filled: bool,
+    pub pixel: Pixel,
+}
+
+pub struct Polyline {
+    pub points: Vec<Point2d>,
+    pub pixel: Pixel,
+}
+
+// one character per pixel, starting at `pos` and going right
+pub struct Text {
+    pub pos: Point2d,
+    pub text: String,
+    pub pixel: Pixel,
+}
+
+impl Ellipse {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let (rx, ry) = (self.radius_x.max(1) as f32, self.radius_y.max(1) as f32);
+        let (dx, dy) = ((x - self.center.x) as f32 / rx, (y - self.center.y) as f32 / ry);
+        dx * dx + dy * dy <= 1.0
+    }
+}
+
+impl Shape2d for Rect {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        let (p1, p2) = (self.p1, self.p2);
+        let corners = [p1, Point2d { x: p2.x, y: p1.y }, p2, Point2d { x: p1.x, y: p2.y }];
+        for i in 0..4 {
+            draw_line(screen, &corners[i], &corners[(i + 1) % 4], &self.pixel);
+        }
+    }
+}
+
+impl Shape2d for FilledRect {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        let (p1, p2) = (self.p1, self.p2);
+        let (xs, ys) = on_screen(screen, (p1.x.min(p2.x), p1.x.max(p2.x)), (p1.y.min(p2.y), p1.y.max(p2.y)));
+        for y in ys {
+            for x in xs.clone() {
+                self.pixel.plot(screen, x, y);
+            }
+        }
+    }
+}
+
+impl Shape2d for Ellipse {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        let (center, radius_x, radius_y) = (self.center, self.radius_x, self.radius_y);
+        let (xs, ys) = on_screen(screen, (center.x - radius_x, center.x + radius_x), (center.y - radius_y, center.y + radius_y));
+        for y in ys {
+            for x in xs.clone() {
+                if !self.contains(x, y) {
+                    continue;
+                }
+                // the outline is every inside pixel with a neighbour outside
+                let on_edge = !self.contains(x - 1, y) || !self.contains(x + 1, y)
+                    || !self.contains(x, y - 1) || !self.contains(x, y + 1);
+                if self.filled || on_edge {
+                    self.pixel.plot(screen, x, y);
+                }
+            }
+        }
+    }
+}
+
+impl Shape2d for Polygon {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        if self.points.is_empty() {
+            return;
+        }
+        if self.filled {
+            fill_polygon(screen, &self.points, &self.pixel);
+        }
+        for i in 0..self.points.len() {
+            draw_line(screen, &self.points[i], &self.points[(i + 1) % self.points.len()], &self.pixel);
+        }
+    }
+}
+
+impl Shape2d for Polyline {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        for pair in self.points.windows(2) {
+            draw_line(screen, &pair[0], &pair[1], &self.pixel);
+        }
+        if self.points.len() == 1 {
+            self.pixel.plot(screen, self.points[0].x, self.points[0].y);
+        }
+    }
+}
+
+impl Shape2d for Text {
+    fn add_to_grid(&self, screen: &mut Screen) {
+        for (i, character) in self.text.chars().enumerate() {
+            let byte = if character.is_ascii() { character as u8 } else { b'?' };
+            Pixel { character: byte, ..self.pixel }.plot(screen, self.pos.x + i as i32, self.pos.y);
+        }
+    }
+}
+
+// The pixels from the first to the last on each axis, both included, cut
+// down to the screen so shapes much bigger than it don't loop over what
+// can't be seen
+fn on_screen(screen: &Screen, (x_min, x_max): (i32, i32), (y_min, y_max): (i32, i32)) -> (RangeInclusive<i32>, RangeInclusive<i32>) {
+    let xs = x_min.max(0)..=x_max.min(screen.width as i32 - 1);
+    let ys = y_min.max(0)..=y_max.min(screen.height as i32 - 1);
+    (xs, ys)
+}
+
+// Bresenham, both ends included
+fn draw_line(screen: &mut Screen, a: &Point2d, b: &Point2d, pixel: &Pixel) {
+    let (dx, dy) = ((b.x - a.x).abs(), -(b.y - a.y).abs());
+    let (step_x, step_y) = (if a.x < b.x { 1 } else { -1 }, if a.y < b.y { 1 } else { -1 });
+    let (mut x, mut y) = (a.x, a.y);
+    let mut error = dx + dy;
+    loop {
+        pixel.plot(screen, x, y);
+        if x == b.x && y == b.y {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+// Even-odd scanline fill, sampling every row at the pixel centers
+fn fill_polygon(screen: &mut Screen, points: &[Point2d], pixel: &Pixel) {
+    let y_min = points.iter().map(|p| p.y).min().unwrap().max(0);
+    let y_max = points.iter().map(|p| p.y).max().unwrap().min(screen.height as i32 - 1);
+    for y in y_min..=y_max {
+        let scan_y = y as f32 + 0.5;
+        let mut crossings: Vec<f32> = vec![];
+        for i in 0..points.len() {
+            let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+            if (a.y as f32 <= scan_y) != (b.y as f32 <= scan_y) {
+                let t = (scan_y - a.y as f32) / (b.y - a.y) as f32;
+                crossings.push(a.x as f32 + t * (b.x - a.x) as f32);
+            }
+        }
+        crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        for span in crossings.chunks(2) {
+            if let [start, end] = span {
+                let (start, end) = ((start.round() as i32).max(0), (end.round() as i32).min(screen.width as i32));
+                for x in start..end {
+                    pixel.plot(screen, x, y);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::CellColor;
+    use crate::display::RenderMode;
+
+    const PIXEL: Pixel = Pixel { character: b'#', z: None, color: CellColor { fg: None, bg: None } };
+
+    // rows top first, like the terminal shows them
+    fn rows(screen: &Screen) -> Vec<String> {
+        screen.to_snapshot().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn rect_draws_the_outline() {
+        let mut screen = Screen::new(5, 4, RenderMode::Ascii);
+        Rect { p1: Point2d { x: 0, y: 0 }, p2: Point2d { x: 3, y: 2 }, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["", "####", "#  #", "####"]);
+    }
+
+    #[test]
+    fn filled_rect_in_either_corner_order() {
+        let mut screen = Screen::new(5, 3, RenderMode::Ascii);
+        FilledRect { p1: Point2d { x: 3, y: 1 }, p2: Point2d { x: 1, y: 0 }, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["", " ###", " ###"]);
+    }
+
+    // far bigger than the screen, only what's on it is visited
+    #[test]
+    fn huge_shapes_are_cut_to_the_screen() {
+        let mut screen = Screen::new(4, 2, RenderMode::Ascii);
+        let (far, near) = (Point2d { x: -1_000_000_000, y: -1_000_000_000 }, Point2d { x: 1_000_000_000, y: 1_000_000_000 });
+        FilledRect { p1: far, p2: near, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["####", "####"]);
+
+        let mut screen = Screen::new(4, 2, RenderMode::Ascii);
+        Ellipse { center: Point2d { x: 2, y: 1 }, radius_x: 100_000_000, radius_y: 100_000_000, filled: true, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["####", "####"]);
+
+        let mut screen = Screen::new(4, 2, RenderMode::Ascii);
+        let points = vec![Point2d { x: -1_000_000_000, y: -10 }, Point2d { x: 1_000_000_000, y: -10 }, Point2d { x: 0, y: 10 }];
+        fill_polygon(&mut screen, &points, &PIXEL);
+        assert_eq!(rows(&screen), vec!["####", "####"]);
+    }
+
+    #[test]
+    fn ellipse_outline_and_fill() {
+        let mut screen = Screen::new(7, 5, RenderMode::Ascii);
+        Ellipse { center: Point2d { x: 3, y: 2 }, radius_x: 3, radius_y: 2, filled: false, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["   #", " ## ##", "#     #", " ## ##", "   #"]);
+
+        let mut screen = Screen::new(7, 5, RenderMode::Ascii);
+        Ellipse { center: Point2d { x: 3, y: 2 }, radius_x: 3, radius_y: 2, filled: true, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["   #", " #####", "#######", " #####", "   #"]);
+    }
+
+    #[test]
+    fn polygon_fills_inside_its_edges() {
+        let mut screen = Screen::new(5, 3, RenderMode::Ascii);
+        let points = vec![Point2d { x: 0, y: 0 }, Point2d { x: 4, y: 0 }, Point2d { x: 2, y: 2 }];
+        Polygon { points, filled: true, pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["  #", " ###", "#####"]);
+    }
+
+    #[test]
+    fn text_goes_right_and_is_cut_at_the_edge() {
+        let mut screen = Screen::new(4, 1, RenderMode::Ascii);
+        Text { pos: Point2d { x: 1, y: 0 }, text: String::from("héllo"), pixel: PIXEL }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec![" h?l"]);
+    }
+
+    // with a depth the shape hides behind what is nearer
+    #[test]
+    fn pixels_with_depth_are_tested() {
+        let mut screen = Screen::new(3, 1, RenderMode::Ascii);
+        screen.z_buf[0][1] = Some(10.0);
+        let pixel = Pixel { z: Some(20), ..PIXEL };
+        Polyline { points: vec![Point2d { x: 0, y: 0 }, Point2d { x: 2, y: 0 }], pixel }.add_to_grid(&mut screen);
+        assert_eq!(rows(&screen), vec!["# #"]);
+        assert_eq!(screen.z_buf[0][0], Some(20.0));
+    }
+}
@@ -0,0 +1,113 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Compares a text snapshot, see Screen::to_snapshot, with the golden file at
+// `path`. With UPDATE_SNAPSHOTS set the golden file is written instead, so new
+// or intentionally changed scenes are recorded by running the same check once.
+// A missing golden file is an error otherwise, or a check that lost its file
+// would pass by recording whatever it drew.
+pub fn check_golden(snapshot: &str, path: &str) -> Result<(), String> {
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(path, snapshot).map_err(|e| format!("Unable to write {}: {}", path, e))?;
+        return Ok(());
+    }
+    if !Path::new(path).exists() {
+        return Err(format!("No golden file {}, run with UPDATE_SNAPSHOTS=1 to record it", path));
+    }
+
+    let golden = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+    if golden == snapshot {
+        return Ok(());
+    }
+    let (golden_lines, snapshot_lines): (Vec<&str>, Vec<&str>) = (golden.lines().collect(), snapshot.lines().collect());
+    for i in 0..golden_lines.len().max(snapshot_lines.len()) {
+        let expected = golden_lines.get(i).copied().unwrap_or("");
+        let actual = snapshot_lines.get(i).copied().unwrap_or("");
+        if expected != actual {
+            return Err(format!(
+                "Snapshot differs from {} at line {}\nexpected: {:?}\nactual:   {:?}",
+                path, i + 1, expected, actual
+            ));
+        }
+    }
+    Err(format!("Snapshot differs from {} in line endings", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::{RenderMode, Screen};
+    use crate::file::read_data_file;
+    use crate::math::Mat4;
+    use crate::point::Point3d;
+    use crate::projection::Camera;
+    use crate::render::{render_offscreen, RenderSettings};
+
+    // data/pyramid.dat, unlit so each face shows its own letter
+    fn check_pyramid(camera: &Camera, mode: RenderMode, name: &str) {
+        let shapes = read_data_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/pyramid.dat")).unwrap();
+        let settings = RenderSettings::default();
+        let screen = render_offscreen(&shapes, &Mat4::IDENTITY, camera, &settings, 40, 20, mode);
+        let path = format!("{}/snapshots/{}.txt", env!("CARGO_MANIFEST_DIR"), name);
+        check_golden(&screen.to_snapshot(), &path).unwrap();
+    }
+
+    // from above and to the left of its front
+    fn corner_camera() -> Camera {
+        Camera::looking_along(Point3d { x: -40.0, y: 60.0, z: 100.0 }, &Point3d { x: 70.0, y: -60.0, z: 130.0 })
+    }
+
+    const PYRAMID_CENTER: Point3d = Point3d { x: 30.0, y: 0.0, z: 230.0 };
+
+    #[test]
+    fn pyramid_ascii() {
+        check_pyramid(&corner_camera(), RenderMode::Ascii, "pyramid_ascii");
+    }
+
+    #[test]
+    fn pyramid_half_block() {
+        check_pyramid(&corner_camera(), RenderMode::HalfBlock, "pyramid_half_block");
+    }
+
+    #[test]
+    fn pyramid_braille() {
+        check_pyramid(&corner_camera(), RenderMode::Braille, "pyramid_braille");
+    }
+
+    #[test]
+    fn pyramid_top_view() {
+        check_pyramid(&Camera::top_view(&PYRAMID_CENTER, 100.0), RenderMode::Ascii, "pyramid_top");
+    }
+
+    #[test]
+    fn pyramid_front_view() {
+        check_pyramid(&Camera::front_view(&PYRAMID_CENTER, 100.0), RenderMode::Ascii, "pyramid_front");
+    }
+
+    #[test]
+    fn pyramid_side_view() {
+        check_pyramid(&Camera::side_view(&PYRAMID_CENTER, 100.0), RenderMode::Ascii, "pyramid_side");
+    }
+
+    // the textured wall of data/wall.dat
+    #[test]
+    fn wall_front_view() {
+        let shapes = read_data_file(concat!(env!("CARGO_MANIFEST_DIR"), "/data/wall.dat")).unwrap();
+        let camera = Camera::front_view(&Point3d { x: 200.0, y: 100.0, z: 400.0 }, 220.0);
+        let settings = RenderSettings::default();
+        let screen = render_offscreen(&shapes, &Mat4::IDENTITY, &camera, &settings, 60, 15, RenderMode::Ascii);
+        check_golden(&screen.to_snapshot(), &format!("{}/snapshots/wall_front.txt", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    }
+
+    #[test]
+    fn missing_golden_is_an_error() {
+        if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            return;
+        }
+        let screen = Screen::new(4, 2, RenderMode::Ascii);
+        let path = format!("{}/snapshots/does_not_exist.txt", env!("CARGO_MANIFEST_DIR"));
+        assert!(check_golden(&screen.to_snapshot(), &path).is_err());
+        assert!(!Path::new(&path).exists());
+    }
+}
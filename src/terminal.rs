@@ -1,8 +1,21 @@
+use std::panic;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver};
 use std::thread;
 extern crate termios;
-use std::io::{self, Read};
-use termios::{ECHO, ICANON, TCSANOW, Termios, tcsetattr};
+use std::io::{self, Read, Write};
+use termios::{ECHO, ICANON, ICRNL, IXON, TCSAFLUSH, TCSANOW, Termios, tcsetattr};
+
+// alternate screen on, cursor hidden, cursor home
+const ENTER_SCREEN: &[u8] = b"\x1b[?1049h\x1b[?25l\x1b[H";
+// reset colors, cursor shown, alternate screen off
+const LEAVE_SCREEN: &[u8] = b"\x1b[0m\x1b[?25h\x1b[?1049l";
+
+static RESIZED: AtomicBool = AtomicBool::new(false);
+static QUIT: AtomicBool = AtomicBool::new(false);
+static SESSION_ACTIVE: AtomicBool = AtomicBool::new(false);
+static ORIGINAL_TERMINAL: OnceLock<Termios> = OnceLock::new();
 
 pub fn spawn_stdin_channel() -> Receiver<u8> {
     let stdin = 0; // couldn't get std::os::unix::io::FromRawFd to work 
@@ -26,10 +39,108 @@ pub fn spawn_stdin_channel() -> Receiver<u8> {
     rx
 }
 
-pub fn get_terminal() -> Termios {
-    Termios::from_fd(0).unwrap()
+pub fn get_terminal() -> io::Result<Termios> {
+    Termios::from_fd(0)
+}
+
+pub fn cleanup(terminal: &Termios) -> io::Result<()> {
+    // TCSAFLUSH drops keys typed while we were running instead of handing
+    // them to the shell
+    tcsetattr(0, TCSAFLUSH, terminal)
+}
+
+// (columns, rows) of the tty on stdout, or None when stdout isn't a terminal
+pub fn get_size() -> Option<(usize, usize)> {
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result != 0 || size.ws_col == 0 || size.ws_row == 0 {
+        return None;
+    }
+    Some((size.ws_col as usize, size.ws_row as usize))
+}
+
+extern "C" fn on_resize(_: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+pub fn watch_resize() {
+    unsafe {
+        libc::signal(libc::SIGWINCH, on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+// true once after every SIGWINCH received since the last call
+pub fn was_resized() -> bool {
+    RESIZED.swap(false, Ordering::Relaxed)
+}
+
+// Owns the terminal while the program runs: alternate screen, hidden cursor
+// and no echo/line buffering. Everything is put back when the session is
+// dropped, when the program panics and when SIGINT/SIGTERM ask it to quit.
+pub struct Session {
+    _private: (),
+}
+
+impl Session {
+    // Fails when stdin isn't a terminal
+    pub fn start() -> io::Result<Session> {
+        let original = get_terminal()?;
+        ORIGINAL_TERMINAL.get_or_init(|| original);
+
+        let mut raw = original;
+        raw.c_lflag &= !(ICANON | ECHO);
+        raw.c_iflag &= !(IXON | ICRNL);
+        tcsetattr(0, TCSANOW, &raw)?;
+        write_escape(ENTER_SCREEN);
+        SESSION_ACTIVE.store(true, Ordering::SeqCst);
+
+        // restore before the default hook prints, so the message ends up on
+        // the normal screen instead of vanishing with the alternate one
+        let previous_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            previous_hook(info);
+        }));
+
+        unsafe {
+            libc::signal(libc::SIGINT, on_quit as extern "C" fn(libc::c_int) as libc::sighandler_t);
+            libc::signal(libc::SIGTERM, on_quit as extern "C" fn(libc::c_int) as libc::sighandler_t);
+        }
+        Ok(Session { _private: () })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+fn restore() {
+    if !SESSION_ACTIVE.swap(false, Ordering::SeqCst) {
+        return;
+    }
+    write_escape(LEAVE_SCREEN);
+    // this also runs from the panic hook, where a second panic would abort
+    // before the message is printed, so a failure is only reported
+    if let Some(original) = ORIGINAL_TERMINAL.get()
+        && let Err(error) = cleanup(original)
+    {
+        eprintln!("couldn't restore the terminal: {}", error);
+    }
+}
+
+fn write_escape(sequence: &[u8]) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(sequence);
+    let _ = stdout.flush();
+}
+
+extern "C" fn on_quit(_: libc::c_int) {
+    QUIT.store(true, Ordering::Relaxed);
 }
 
-pub fn cleanup(terminal: &Termios) {
-    tcsetattr(0, TCSANOW, terminal).unwrap();
+// set by SIGINT/SIGTERM, the game loop should exit so the session can drop
+pub fn quit_requested() -> bool {
+    QUIT.load(Ordering::Relaxed)
 }
@@ -0,0 +1,15 @@
+// Helpers shared by the tests of several modules
+use crate::display::{RenderMode, Screen};
+use crate::point::{self, Point3d, ORIGIN};
+use crate::projection::{Camera, View};
+
+// at the origin looking along +z on a 40x20 screen, so the depth of a point
+// is its z and the near plane is at z = 10
+pub fn view() -> View {
+    Camera::new(ORIGIN).view(&Screen::new(40, 20, RenderMode::Ascii))
+}
+
+// the same point, give or take rounding
+pub fn close(a: &Point3d, b: &Point3d) -> bool {
+    point::distance(a, b) < 1e-4
+}
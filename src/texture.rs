@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::color::Rgb;
+
+// A grid of glyphs, with an optional color for each of them. In a texture
+// file the glyph rows come first, then optionally a line of ---, rows of
+// color keys of the same size, another --- and the palette, one
+// `<key> <r> <g> <b>` per line. Keys missing from the palette are uncolored.
+//
+//   ##.##.##
+//   ..#..#..
+//   ---
+//   rrbrrbrr
+//   ..r..r..
+//   ---
+//   r 170 60 40
+//   b 120 110 100
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    // row 0 is the first line of the file
+    pub glyphs: Vec<Vec<u8>>,
+    pub colors: Vec<Vec<Option<Rgb>>>,
+}
+
+impl Texture {
+    pub fn load(path: &Path) -> Result<Texture, String> {
+        let mut data = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut data))
+            .map_err(|e| format!("Unable to read texture {}: {}", path.display(), e))?;
+        Ok(Texture::parse(&data))
+    }
+
+    pub fn parse(text: &str) -> Texture {
+        let sections: Vec<Vec<&str>> = text
+            .split("\n---")
+            .map(|s| s.lines().filter(|l| !l.is_empty()).collect())
+            .collect();
+        let rows = &sections[0];
+        let height = rows.len();
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        if width == 0 {
+            panic!("Texture has no glyphs");
+        }
+        let glyphs: Vec<Vec<u8>> = rows
+            .iter()
+            .map(|r| (0..width).map(|x| *r.as_bytes().get(x).unwrap_or(&b' ')).collect())
+            .collect();
+
+        let mut palette: HashMap<u8, Rgb> = HashMap::new();
+        if let Some(entries) = sections.get(2) {
+            for entry in entries {
+                let parts: Vec<&str> = entry.split_whitespace().collect();
+                let channel = |i: usize| parts[i].parse::<u8>().expect("Bad color in texture palette");
+                palette.insert(parts[0].as_bytes()[0], Rgb::new(channel(1), channel(2), channel(3)));
+            }
+        }
+        let keys: &[&str] = sections.get(1).map(|s| s.as_slice()).unwrap_or(&[]);
+        let colors: Vec<Vec<Option<Rgb>>> = (0..height)
+            .map(|y| (0..width).map(|x| {
+                let key = keys.get(y).and_then(|r| r.as_bytes().get(x))?;
+                palette.get(key).copied()
+            }).collect())
+            .collect();
+
+        Texture { width, height, glyphs, colors }
+    }
+
+    // (0, 0) is the top left corner and (1, 1) the bottom right, outside of
+    // that the texture repeats
+    pub fn sample(&self, u: f32, v: f32) -> (u8, Option<Rgb>) {
+        let x = ((u.rem_euclid(1.0) * self.width as f32) as usize).min(self.width - 1);
+        let y = ((v.rem_euclid(1.0) * self.height as f32) as usize).min(self.height - 1);
+        (self.glyphs[y][x], self.colors[y][x])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRICKS: &str = "#.#\n.#\n---\nrbr\nr\n---\nr 170 60 40\nb 10 20 30\n";
+
+    #[test]
+    fn parse_pads_short_rows() {
+        let texture = Texture::parse(BRICKS);
+        assert_eq!((texture.width, texture.height), (3, 2));
+        assert_eq!(texture.glyphs, vec![b"#.#".to_vec(), b".# ".to_vec()]);
+    }
+
+    // keys missing from their row or from the palette leave the glyph
+    // uncolored
+    #[test]
+    fn parse_reads_color_rows() {
+        let texture = Texture::parse(BRICKS);
+        let (red, blue) = (Rgb::new(170, 60, 40), Rgb::new(10, 20, 30));
+        assert!(texture.colors[0] == vec![Some(red), Some(blue), Some(red)]);
+        assert!(texture.colors[1] == vec![Some(red), None, None]);
+    }
+
+    #[test]
+    fn parse_without_colors() {
+        let texture = Texture::parse("ab\ncd\n");
+        assert!(texture.colors.iter().flatten().all(|c| c.is_none()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Texture has no glyphs")]
+    fn parse_rejects_an_empty_texture() {
+        Texture::parse("\n---\n");
+    }
+
+    #[test]
+    fn sample_picks_the_cell_under_the_uv() {
+        let texture = Texture::parse("abc\ndef\n");
+        assert_eq!(texture.sample(0.0, 0.0).0, b'a');
+        assert_eq!(texture.sample(0.5, 0.0).0, b'b');
+        assert_eq!(texture.sample(0.99, 0.99).0, b'f');
+        assert_eq!(texture.sample(0.2, 0.7).0, b'd');
+    }
+
+    // outside of 0 to 1 the texture repeats, the same way on both sides
+    #[test]
+    fn sample_wraps_around() {
+        let texture = Texture::parse("abc\ndef\n");
+        assert_eq!(texture.sample(1.0, 1.0).0, b'a');
+        assert_eq!(texture.sample(2.5, 0.0).0, b'b');
+        assert_eq!(texture.sample(-0.1, 0.0).0, b'c');
+        assert_eq!(texture.sample(0.0, -0.25).0, b'd');
+        assert_eq!(texture.sample(-3.5, 4.6).0, b'e');
+    }
+
+    #[test]
+    fn sample_returns_the_color() {
+        let texture = Texture::parse(BRICKS);
+        assert!(texture.sample(0.5, 0.0) == (b'.', Some(Rgb::new(10, 20, 30))));
+        assert!(texture.sample(0.5, 0.5) == (b'#', None));
+    }
+
+    #[test]
+    fn load_reads_the_brick_texture() {
+        let texture = Texture::load(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/data/brick.txt"))).unwrap();
+        assert_eq!((texture.width, texture.height), (12, 4));
+        assert!(texture.colors[0][0] == Some(Rgb::new(150, 150, 140)));
+    }
+}
@@ -1,20 +1,52 @@
-use crate::constants::{HEIGHT, WIDTH};
 use crate::line::Line2dZ;
 use crate::point::{Point2d, Point2dZ, Point3d};
-use crate::projection::Camera;
+use crate::projection::View;
 use crate::display::{Pixel, Screen};
 use crate::display;
+use crate::color::{CellColor, Rgb};
+use crate::light;
+use crate::math::Transform;
+use crate::material::Material;
+use crate::texture::Texture;
 use std::cmp;
+use std::rc::Rc;
+
+// Which side of a triangle isn't drawn. Seen from the front, the points of a
+// triangle go around clockwise, and normal() points out of the front.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Culling {
+    None,
+    Back,
+    Front,
+}
 
 pub struct Triangle3d {
     pub points: [Point3d; 3],
-    pub fill: u8,
-    pub border: Option<u8>,
+    // per vertex normals for smooth shading, see light::compute_vertex_normals
+    pub normals: Option<[Point3d; 3]>,
+    // (u, v) of each corner in the material's texture, which replaces the fill
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub material: Rc<Material>,
 }
 pub struct Triangle2d {
     pub points: [Point2dZ; 3],
     pub fill: u8,
     pub border: Option<u8>,
+    pub color: CellColor,
+    pub border_color: CellColor,
+    // light intensity at each vertex, interpolated across the face to pick
+    // the glyph and darken the color of every cell
+    pub shade: Option<[f32; 3]>,
+    // the corners before projection, interpolated into Screen::world
+    pub world_points: [Point3d; 3],
+    pub culling: Culling,
+    pub uvs: Option<[(f32, f32); 3]>,
+    pub texture: Option<Rc<Texture>>,
+    // above 0.0 the face is blended over what is already drawn and doesn't
+    // write depth, see Material
+    pub transparency: f32,
+    // false for an orthographic projection, whose depth doesn't shrink things
+    pub perspective: bool,
 }
 
 //Actually double or triple the area, but it doesn't matter for what I'm using it for
@@ -28,10 +60,33 @@ pub fn area_from_points(p1: &Point2d, p2: &Point2d, p3: &Point2d) -> i64 {
 
 impl Triangle2d {
     pub fn add_to_grid(&self, screen: &mut Screen) {
-        let y_max = cmp::min( HEIGHT as i32 - 1, cmp::max(cmp::max(self.points[0].y as i32, self.points[1].y as i32), self.points[2].y as i32), );
-        let y_min = cmp::max(0, cmp::min(cmp::min(self.points[0].y as i32, self.points[1].y as i32), self.points[2].y as i32));
-        let x_max = cmp::min(WIDTH as i32 - 1, cmp::max(cmp::max(self.points[0].x as i32, self.points[1].x as i32), self.points[2].x as i32));
-        let x_min = cmp::max(0, cmp::min(cmp::min(self.points[0].x as i32, self.points[1].x as i32), self.points[2].x as i32));
+        let texture = match (&self.texture, self.uvs) {
+            (Some(texture), Some(uvs)) => Some((texture.as_ref(), uvs)),
+            _ => None,
+        };
+        self.fill_with(screen, self.fill, self.shade, texture);
+    }
+
+    // For the braille mode: the face is drawn blank so it only hides what is
+    // behind it, and the edges are drawn on top of it
+    pub fn add_wireframe_to_grid(&self, screen: &mut Screen) {
+        self.fill_with(screen, b' ', None, None);
+        match self.border {
+            Some(b) if b != b' ' => self.add_edges_to_grid(screen, b, self.border_color),
+            _ => self.add_edges_to_grid(screen, self.fill, self.color),
+        }
+    }
+
+    // A texture gives the glyph and, where it has one, the color of every
+    // cell, the shade then only darkens the color
+    fn fill_with(&self, screen: &mut Screen, fill: u8, shade: Option<[f32; 3]>, texture: Option<(&Texture, [(f32, f32); 3])>) {
+        if self.transparency >= 1.0 {
+            return;
+        }
+        let y_max = cmp::min( screen.height as i32 - 1, cmp::max(cmp::max(self.points[0].y, self.points[1].y), self.points[2].y), );
+        let y_min = cmp::max(0, cmp::min(cmp::min(self.points[0].y, self.points[1].y), self.points[2].y));
+        let x_max = cmp::min(screen.width as i32 - 1, cmp::max(cmp::max(self.points[0].x, self.points[1].x), self.points[2].x));
+        let x_min = cmp::max(0, cmp::min(cmp::min(self.points[0].x, self.points[1].x), self.points[2].x));
 
         let triangle_area = self.area();
         let (p1, p2, p3) = (
@@ -40,6 +95,8 @@ impl Triangle2d {
             self.points[2].drop_z()
         );
         let (z1, z2, z3) = (self.points[0].z as i64, self.points[1].z as i64, self.points[2].z as i64);
+        let [w1, w2, w3] = self.world_points;
+        let divisor = |z: i64| if self.perspective { z.max(1) as f32 } else { 1.0 };
 
         for x in x_min..=x_max {
             for y in y_min..=y_max {
@@ -50,18 +107,37 @@ impl Triangle2d {
 
                 if sub1 + sub2 + sub3 == triangle_area as i64 {
                     let z = ((sub1 * z1) + (sub2 * z2) + (sub3 * z3)) as f32 / triangle_area as f32 + 5.0;
-                    match screen.z_buf[y as usize][x as usize] {
-                        None => {
-                            screen.grid[y as usize][x as usize] = self.fill;
-                            screen.z_buf[y as usize][x as usize] = Some(z);
-                        },
-                        Some(pixel_z) => {
-                            if z < pixel_z {
-                                screen.grid[y as usize][x as usize] = self.fill;
-                                screen.z_buf[y as usize][x as usize] = Some(z);
-                            }
+                    if screen.z_buf[y as usize][x as usize].is_some_and(|pixel_z| z >= pixel_z) {
+                        continue;
+                    }
+                    // perspective correct, the screen space weights divided by the
+                    // same depth project() divides by
+                    let (b1, b2, b3) = (sub1 as f32 / divisor(z1), sub2 as f32 / divisor(z2), sub3 as f32 / divisor(z3));
+                    let total = (b1 + b2 + b3).max(f32::MIN_POSITIVE);
+
+                    let intensity = shade.map(|[i1, i2, i3]| (sub1 as f32 * i1 + sub2 as f32 * i2 + sub3 as f32 * i3) / triangle_area as f32);
+                    let (character, color) = match (texture, intensity) {
+                        (Some((texture, [uv1, uv2, uv3])), _) => {
+                            let u = (b1 * uv1.0 + b2 * uv2.0 + b3 * uv3.0) / total;
+                            let v = (b1 * uv1.1 + b2 * uv2.1 + b3 * uv3.1) / total;
+                            let (character, texel_color) = texture.sample(u, v);
+                            let color = CellColor { fg: texel_color.or(self.color.fg), ..self.color };
+                            (character, light::shade_color(&color, intensity.unwrap_or(1.0)))
                         },
+                        (None, Some(intensity)) => (light::ramp_glyph(intensity), light::shade_color(&self.color, intensity)),
+                        (None, None) => (fill, self.color),
+                    };
+                    if self.transparency > 0.0 {
+                        self.blend(screen, x as usize, y as usize, character, color);
+                        continue;
                     }
+                    screen.set(x as usize, y as usize, character, color);
+                    screen.z_buf[y as usize][x as usize] = Some(z);
+                    screen.world[y as usize][x as usize] = Some(Point3d {
+                        x: (b1 * w1.x + b2 * w2.x + b3 * w3.x) / total,
+                        y: (b1 * w1.y + b2 * w2.y + b3 * w3.y) / total,
+                        z: (b1 * w1.z + b2 * w2.z + b3 * w3.z) / total,
+                    });
                 }
             }
         }
@@ -98,7 +174,7 @@ impl Triangle2d {
         // println!("slope_min: {}", slope_min); println!("slope_max: {}", slope_max); println!("left: {}", left_point); println!("right: {}", right_point); println!("mid: {}", mid_point); println!("low: {}", low_point); println!("high: {}", high_point);
         for x in left_point.x as i32..mid_point.x as i32 {
             for y in min.ceil() as i32..max as i32 {
-                if x>=0 && y>= 0 && x<WIDTH as i32 && y<HEIGHT as i32 {
+                if screen.contains(x, y) {
                     let p = Point2d {x, y};
                     let sub1 = area_from_points(&p, &p2d_2, &p2d_3);
                     let sub2 = area_from_points(&p, &p2d_1, &p2d_3);
@@ -131,7 +207,7 @@ impl Triangle2d {
         }
         for x in mid_point.x as i32..=right_point.x as i32 {
             for y in min.ceil() as i32..max as i32 {
-                if x>=0 && y>= 0 && x<WIDTH as i32 && y<HEIGHT as i32 {
+                if screen.contains(x, y) {
                     let p = Point2d {x, y};
                     let sub1 = area_from_points(&p, &p2d_2, &p2d_3);
                     let sub2 = area_from_points(&p, &p2d_1, &p2d_3);
@@ -166,23 +242,54 @@ impl Triangle2d {
     }*/
     
     
+    // A blank glyph keeps the one underneath, the colors are mixed by how
+    // opaque the face is. Depth and world position are left to what's behind.
+    fn blend(&self, screen: &mut Screen, x: usize, y: usize, character: u8, color: CellColor) {
+        let opacity = 1.0 - self.transparency;
+        let below = screen.color[y][x];
+        let mix = |below: Option<Rgb>, over: Option<Rgb>| match (below, over) {
+            (Some(below), Some(over)) => Some(below.lerp(&over, opacity)),
+            (None, over) => over,
+            (below, None) => below,
+        };
+        let glyph = if character == b' ' { screen.grid[y][x] } else { character };
+        screen.set(x, y, glyph, CellColor { fg: mix(below.fg, color.fg), bg: mix(below.bg, color.bg) });
+    }
+
     pub fn add_border_to_grid(&self, screen: &mut Screen) {
         if let Some(b) = self.border {
-            Line2dZ { p1: self.points[0], p2: self.points[1], character: b}
-                .add_to_grid(screen);
-            Line2dZ { p1: self.points[0], p2: self.points[2], character: b}
-                .add_to_grid(screen);
-            Line2dZ { p1: self.points[1], p2: self.points[2], character: b}
-                .add_to_grid(screen);
+            self.add_edges_to_grid(screen, b, self.border_color);
         }
     }
 
+    fn add_edges_to_grid(&self, screen: &mut Screen, character: u8, color: CellColor) {
+        let writes_depth = self.transparency == 0.0;
+        Line2dZ { p1: self.points[0], p2: self.points[1], character, color, writes_depth }
+            .add_to_grid(screen);
+        Line2dZ { p1: self.points[0], p2: self.points[2], character, color, writes_depth }
+            .add_to_grid(screen);
+        Line2dZ { p1: self.points[1], p2: self.points[2], character, color, writes_depth }
+            .add_to_grid(screen);
+    }
+
     pub fn area(&self) -> i32 {
+        self.signed_area().abs()
+    }
+    // positive when the points go around counterclockwise on the screen
+    pub fn signed_area(&self) -> i32 {
         let mut area: i32 = 0;
         area += self.points[0].x * (self.points[1].y - self.points[2].y);
         area += self.points[1].x * (self.points[2].y - self.points[0].y);
         area += self.points[2].x * (self.points[0].y - self.points[1].y);
-        return area.abs();
+        area
+    }
+    // true when the side facing the camera is the one that isn't drawn
+    pub fn is_culled(&self) -> bool {
+        match self.culling {
+            Culling::None => false,
+            Culling::Back => self.signed_area() > 0,
+            Culling::Front => self.signed_area() < 0,
+        }
     }
     // pub fn is_inside(&self, p: Point2d) -> bool {
     //     let t1 = Triangle2d { points: [Point2d {x:p.x, y:p.y}, Point2d {x:self.points[0].x, y:self.points[0].y}, Point2d{x:self.points[1].x, y:self.points[1].y}], fill: b'X', border: None };
@@ -200,80 +307,88 @@ impl Triangle2d {
 
 
 impl Triangle3d {
-    pub fn project(&self, camera: &Camera) -> Triangle2d {
-       let p1 = self.points[0]
-           .rotate_y(&camera.pos, camera.rot.x)
-           .rotate_x(&camera.pos, camera.rot.y)
-           .rotate_z(&camera.pos, camera.rot.z)
-           .project(camera);
-       let p2 = self.points[1]
-           .rotate_y(&camera.pos, camera.rot.x)
-           .rotate_x(&camera.pos, camera.rot.y)
-           .rotate_z(&camera.pos, camera.rot.z)
-           .project(camera);
-       let p3 = self.points[2]
-           .rotate_y(&camera.pos, camera.rot.x)
-           .rotate_x(&camera.pos, camera.rot.y)
-           .rotate_z(&camera.pos, camera.rot.z)
-           .project(camera);
+    // for triangles that went through clip_near
+    pub fn project(&self, view: &View) -> Triangle2d {
+       let p1 = view.project_clipped(&self.points[0]);
+       let p2 = view.project_clipped(&self.points[1]);
+       let p3 = view.project_clipped(&self.points[2]);
        return Triangle2d {
            points: match (p1, p2, p3) {
                (Some(p1), Some(p2), Some(p3)) => [p1, p2, p3],
                _ => [Point2dZ { x: -1, y: -1, z: 0}, Point2dZ { x: -1, y: -1, z: 0}, Point2dZ { x: -1, y: -1, z: 0}],
            },
-           fill: self.fill,
-           border: self.border,
+           fill: self.material.fill,
+           border: self.material.border,
+           color: self.material.color,
+           border_color: self.material.border_color,
+           shade: None,
+           world_points: self.points,
+           culling: self.material.culling,
+           uvs: self.uvs,
+           texture: self.material.texture.clone(),
+           transparency: self.material.transparency,
+           perspective: view.perspective,
        };
    }   
 
-   pub fn rotate_y(&self, center: &Point3d, rads: f32) -> Triangle3d {
+   // a triangle with the same appearance at other points
+   pub fn with_points(&self, points: [Point3d; 3]) -> Triangle3d {
        Triangle3d {
-            points: [
-               self.points[0].rotate_y(center, rads),
-               self.points[1].rotate_y(center, rads),
-               self.points[2].rotate_y(center, rads),
-            ],
-            border: self.border,
-            fill: self.fill,
+           points,
+           ..self.clone()
        }
    }
-   pub fn rotate_x(&self, center: &Point3d, rads: f32) -> Triangle3d {
+
+   // Moved by a model transform, into world space. A mirroring transform
+   // swaps two corners so the front stays the side that's clockwise.
+   pub fn transformed(&self, transform: &Transform) -> Triangle3d {
+       let order = if transform.mirrors { [0, 2, 1] } else { [0, 1, 2] };
        Triangle3d {
-            points: [
-               self.points[0].rotate_x(center, rads),
-               self.points[1].rotate_x(center, rads),
-               self.points[2].rotate_x(center, rads),
-            ],
-            border: self.border,
-            fill: self.fill,
+           points: order.map(|i| transform.point(&self.points[i])),
+           normals: self.normals.map(|n| order.map(|i| transform.normal(&n[i]))),
+           uvs: self.uvs.map(|uv| order.map(|i| uv[i])),
+           material: self.material.clone(),
        }
    }
-   pub fn rotate_z(&self, center: &Point3d, rads: f32) -> Triangle3d {
-       Triangle3d {
-            points: [
-               self.points[0].rotate_z(center, rads),
-               self.points[1].rotate_z(center, rads),
-               self.points[2].rotate_z(center, rads),
-            ],
-            border: self.border,
-            fill: self.fill,
-       }
+
+   // unit normal, out of the front face, see Culling
+   pub fn normal(&self) -> Point3d {
+       let a = self.points[1].minus(&self.points[0]);
+       let b = self.points[2].minus(&self.points[0]);
+       a.cross(&b).normalized()
    }
-   pub fn rotate_y_mut(&mut self, center: &Point3d, rads: f32) {
-       for p in &mut self.points {
-            p.rotate_y_mut(center, rads);
+
+   // The part of the triangle in front of the camera's near plane: nothing,
+   // the triangle itself, or one or two new ones where the plane cuts it.
+   // Normals and UVs are interpolated along the cut edges and the winding is
+   // kept, so culling still sees the same front.
+   pub fn clip_near(&self, view: &View) -> Vec<Triangle3d> {
+       let depths = self.points.map(|p| view.depth(&p));
+       if depths.iter().all(|d| *d >= view.near) {
+           return vec![self.clone()];
        }
-   }
-   pub fn rotate_x_mut(&mut self, center: &Point3d, rads: f32) {
-       for p in &mut self.points {
-            p.rotate_x_mut(center, rads);
+       // corners as indices, or as a fraction of the way from one to the next
+       let mut polygon: Vec<(usize, usize, f32)> = vec![];
+       for i in 0..3 {
+           let j = (i + 1) % 3;
+           if depths[i] >= view.near {
+               polygon.push((i, i, 0.0));
+           }
+           if (depths[i] >= view.near) != (depths[j] >= view.near) {
+               polygon.push((i, j, (view.near - depths[i]) / (depths[j] - depths[i])));
+           }
        }
-   }
-   pub fn rotate_z_mut(&mut self, center: &Point3d, rads: f32) {
-       for p in &mut self.points {
-            p.rotate_z_mut(center, rads);
+       let mut pieces = vec![];
+       for k in 2..polygon.len() {
+           let corners = [polygon[0], polygon[k - 1], polygon[k]];
+           let mut piece = self.with_points(corners.map(|(a, b, t)| self.points[a].lerp(&self.points[b], t)));
+           piece.normals = self.normals.map(|n| corners.map(|(a, b, t)| n[a].lerp(&n[b], t).normalized()));
+           piece.uvs = self.uvs.map(|uv| corners.map(|(a, b, t)| (uv[a].0 + (uv[b].0 - uv[a].0) * t, uv[a].1 + (uv[b].1 - uv[a].1) * t)));
+           pieces.push(piece);
        }
+       pieces
    }
+
    pub fn center(&self) -> Point3d {
        Point3d {
            x: (self.points[0].x + self.points[1].x + self.points[2].x) / 3.0,
@@ -287,9 +402,108 @@ impl Clone for Triangle3d {
     fn clone(&self) -> Self {
         Triangle3d {
             points: [self.points[0].clone(), self.points[1].clone(), self.points[2].clone()],
-            fill: self.fill,
-            border: self.border,
+            normals: self.normals,
+            uvs: self.uvs,
+            material: self.material.clone(),
         }
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::RenderMode;
+    use crate::projection::Camera;
+    use crate::testing::{close, view};
+
+    // x, y, z, u and v at each corner, with normals along x at z = 0 and
+    // along y at z = 20
+    fn triangle(points: [Point3d; 3]) -> Triangle3d {
+        let normal = |p: &Point3d| if p.z < 10.0 { Point3d { x: 1.0, y: 0.0, z: 0.0 } } else { Point3d { x: 0.0, y: 1.0, z: 0.0 } };
+        Triangle3d {
+            points,
+            normals: Some(points.map(|p| normal(&p))),
+            uvs: Some(points.map(|p| (p.x / 10.0, p.y / 10.0))),
+            material: Rc::new(Material::default()),
+        }
+    }
+
+    // every corner is on or in front of the near plane and keeps the UV and
+    // normal its position calls for
+    fn check_piece(piece: &Triangle3d, original: &Triangle3d) {
+        let uvs = piece.uvs.unwrap();
+        let normals = piece.normals.unwrap();
+        for i in 0..3 {
+            let p = piece.points[i];
+            assert!(p.z >= 10.0 - 1e-4, "corner {} is behind the near plane", p);
+            assert!((uvs[i].0 - p.x / 10.0).abs() < 1e-4 && (uvs[i].1 - p.y / 10.0).abs() < 1e-4);
+            // the normal turns from x to y as z goes from 0 to 20
+            let t = p.z / 20.0;
+            let expected = Point3d { x: 1.0 - t, y: t, z: 0.0 }.normalized();
+            assert!(close(&normals[i], &expected), "normal {} at {}", normals[i], p);
+        }
+        assert!(piece.normal().dot(&original.normal()) > 0.99, "winding flipped");
+    }
+
+    #[test]
+    fn clip_near_keeps_a_triangle_in_front() {
+        let t = triangle([Point3d { x: 0.0, y: 0.0, z: 20.0 }, Point3d { x: 0.0, y: 10.0, z: 20.0 }, Point3d { x: 10.0, y: 0.0, z: 20.0 }]);
+        let pieces = t.clip_near(&view());
+        assert_eq!(pieces.len(), 1);
+        for i in 0..3 {
+            assert!(close(&pieces[0].points[i], &t.points[i]));
+        }
+    }
+
+    #[test]
+    fn clip_near_splits_with_one_corner_behind() {
+        let t = triangle([Point3d { x: 0.0, y: 0.0, z: 0.0 }, Point3d { x: 0.0, y: 10.0, z: 20.0 }, Point3d { x: 10.0, y: 0.0, z: 20.0 }]);
+        let pieces = t.clip_near(&view());
+        assert_eq!(pieces.len(), 2);
+        for piece in &pieces {
+            check_piece(piece, &t);
+        }
+        // the cuts are half way along the edges from the corner behind
+        let corners: Vec<Point3d> = pieces.iter().flat_map(|p| p.points).collect();
+        assert!(corners.iter().any(|p| close(p, &Point3d { x: 0.0, y: 5.0, z: 10.0 })));
+        assert!(corners.iter().any(|p| close(p, &Point3d { x: 5.0, y: 0.0, z: 10.0 })));
+    }
+
+    #[test]
+    fn clip_near_shrinks_with_two_corners_behind() {
+        let t = triangle([Point3d { x: 0.0, y: 0.0, z: 0.0 }, Point3d { x: 0.0, y: 10.0, z: 0.0 }, Point3d { x: 10.0, y: 0.0, z: 20.0 }]);
+        let pieces = t.clip_near(&view());
+        assert_eq!(pieces.len(), 1);
+        check_piece(&pieces[0], &t);
+        let corners = pieces[0].points;
+        assert!(corners.iter().any(|p| close(p, &Point3d { x: 10.0, y: 0.0, z: 20.0 })));
+        assert!(corners.iter().any(|p| close(p, &Point3d { x: 5.0, y: 5.0, z: 10.0 })));
+        assert!(corners.iter().any(|p| close(p, &Point3d { x: 5.0, y: 0.0, z: 10.0 })));
+    }
+
+    #[test]
+    fn clip_near_drops_a_triangle_behind() {
+        let t = triangle([Point3d { x: 0.0, y: 0.0, z: 0.0 }, Point3d { x: 0.0, y: 10.0, z: 5.0 }, Point3d { x: 10.0, y: 0.0, z: -20.0 }]);
+        assert!(t.clip_near(&view()).is_empty());
+    }
+
+    // the cut corners still project from a turned camera far from the origin
+    #[test]
+    fn clipped_corners_project() {
+        let camera = Camera::from_euler(Point3d { x: 5000.0, y: 300.0, z: -7000.0 }, 0.7, -0.3, 0.2);
+        let view = camera.view(&Screen::new(40, 20, RenderMode::Ascii));
+        let (pos, forward, right) = (camera.pos, camera.forward(), camera.right());
+        let at = |ahead: f32, side: f32| Point3d {
+            x: pos.x + forward.x * ahead + right.x * side,
+            y: pos.y + forward.y * ahead + right.y * side,
+            z: pos.z + forward.z * ahead + right.z * side,
+        };
+        let t = triangle([at(-300.0, -1.0), at(700.0, 0.0), at(-100.0, 1.0)]);
+        let pieces = t.clip_near(&view);
+        assert!(!pieces.is_empty());
+        for piece in &pieces {
+            assert!(piece.points.iter().all(|p| view.project_clipped(p).is_some()));
+        }
+    }
+}